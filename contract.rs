@@ -1,16 +1,78 @@
 //! Timed Sell Order — Solana Anchor program
 //! Allows a seller to delegate (approve) SPL tokens to a PDA so that anyone can
 //! purchase them before a user‑defined deadline.  After the deadline the seller
-//! can cancel and the delegate is revoked.
+//! can cancel and the delegate is revoked.  Alternatively the tokens can be
+//! escrowed in a program‑owned vault for the lifetime of the order.  Both the
+//! legacy SPL Token program and Token‑2022 are supported.
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Approve, Revoke, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_spl::token_2022::{
+    self as token_2022,
+    spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::{TransferFee, TransferFeeConfig},
+            BaseStateWithExtensions, StateWithExtensions,
+        },
+    },
+};
+use anchor_spl::token_interface::{
+    self, Approve, CloseAccount, Mint, Revoke, TokenAccount, TokenInterface, TransferChecked,
+};
+use pyth_sdk_solana::load_price_feed_from_account_info;
 
 // -----------------------------------------------------------------------------
 // Declare program id (update with `solana address -k target/idl/…` after deploy)
 // -----------------------------------------------------------------------------
 declare_id!("S3LLorD3r2hV1W6C4METH1NVQGdcvJxdKmxhZz7D3Lg");
 
+/// Basis‑point denominator used for all fee maths
+pub const BPS_DENOMINATOR: u64 = 10_000;
+/// Upper bound for the protocol fee (100%)
+pub const MAX_FEE_BPS: u16 = 10_000;
+/// Most orders a single `buy_batch` may sweep.  Each leg costs seven account
+/// keys and three CPIs (~35k CU), so four legs stay within both the legacy
+/// transaction size and the default 200k compute budget.
+pub const MAX_BATCH_LEGS: usize = 4;
+/// Remaining accounts supplied per `buy_batch` leg
+pub const BATCH_LEG_ACCOUNTS: usize = 7;
+/// Longest an order may stay open, in seconds (one year)
+pub const MAX_DURATION: i64 = 365 * 24 * 60 * 60;
+/// Shortest an order may stay open, in seconds (five minutes)
+pub const MIN_DURATION: i64 = 5 * 60;
+/// Maximum number of buyers a single order whitelist can hold
+pub const MAX_WHITELIST: usize = 32;
+/// Maximum number of quantity price tiers on an order
+pub const MAX_TIERS: usize = 4;
+/// Maximum number of mints the admin allowlist can hold
+pub const MAX_ALLOWED_MINTS: usize = 64;
+/// Maximum number of orders `cancel_all` tears down in one call
+pub const MAX_CANCEL_ALL: usize = 8;
+/// Remaining accounts per order in `cancel_all`
+pub const CANCEL_ALL_ACCOUNTS: usize = 3;
+/// Maximum number of live orders a `SellerIndex` can track
+pub const MAX_SELLER_ORDERS: usize = 32;
+/// Maximum number of live orders a `MintIndex` can track
+pub const MAX_MINT_ORDERS: usize = 32;
+/// Most `price_step_bps` increases an order may go through
+pub const MAX_PRICE_STEPS: u64 = 64;
+/// Most extra token accounts a delegated order may draw from
+pub const MAX_SOURCE_ACCOUNTS: usize = 3;
+/// Fills kept by a `FillFeed` before the oldest is overwritten
+pub const FILL_FEED_LEN: usize = 8;
+/// Decimals of `usd_price_per_token` (micro‑USD per token base unit)
+pub const USD_DECIMALS: u32 = 6;
+/// Oldest oracle price a `UsdOracle` buy accepts, in seconds
+pub const MAX_ORACLE_AGE: u64 = 60;
+/// Instruction discriminator of the `callback_program` entrypoint, the
+/// Anchor sighash of `on_fill` (`sha256("global:on_fill")[..8]`)
+pub const FILL_CALLBACK_DISCRIMINATOR: [u8; 8] = [133, 76, 152, 104, 24, 119, 244, 223];
+
 // ============================================================================
 // Program entrypoints
 // ============================================================================
@@ -19,228 +81,6597 @@ pub mod timed_sell_order {
     use super::*;
 
     /// Create a new sell order and delegate `amount` tokens from the seller’s
-    /// token account to the program‑derived *order authority*.
-    pub fn create_sell_order(
-        ctx: Context<CreateSellOrder>,
-        amount: u64,
-        price_per_token: u64, // denominated in **lamports** for simplicity
-        deadline: i64,        // unix timestamp (UTC)
-    ) -> Result<()> {
-        // --- sanity checks ---------------------------------------------------
-        require!(amount > 0, SellError::InvalidAmount);
-        require!(price_per_token > 0, SellError::InvalidPrice);
-        require!(deadline > Clock::get()?.unix_timestamp, SellError::DeadlineInPast);
+    /// token account to the program‑derived *order authority*.  A token account
+    /// can back several orders with distinct `order_id`s; reusing a live id
+    /// fails with `OrderAlreadyExists`.  With `percentage_of_balance` the
+    /// amount is that share of the account's balance not yet delegated to
+    /// other orders.
+    pub fn create_sell_order(ctx: Context<CreateSellOrder>, params: OrderParams) -> Result<()> {
+        let mut params = params;
+        require!(!ctx.accounts.config.paused, SellError::ProgramPaused);
+        let uncommitted = ctx
+            .accounts
+            .seller_token_account
+            .amount
+            .saturating_sub(delegated_to(
+                &ctx.accounts.seller_token_account,
+                &ctx.accounts.order_authority,
+            ));
+        size_from_balance(&mut params, uncommitted)?;
+        validate_nft(&params, &ctx.accounts.token_mint)?;
+        check_mint_allowed(&ctx.accounts.mint_allowlist, &ctx.accounts.token_mint.key())?;
 
         // --- persist order data ---------------------------------------------
         let order = &mut ctx.accounts.sell_order;
-        order.seller = ctx.accounts.seller.key();
-        order.token_mint = ctx.accounts.seller_token_account.mint;
-        order.token_account = ctx.accounts.seller_token_account.key();
-        order.amount_remaining = amount;
-        order.price_per_token = price_per_token;
-        order.deadline = deadline;
-        order.bump = *ctx.bumps.get("order_authority").unwrap();
+        order.open(
+            &params,
+            ctx.accounts.seller.key(),
+            ctx.accounts.seller_token_account.mint,
+            ctx.accounts.seller_token_account.key(),
+            false,
+            Clock::get()?.unix_timestamp,
+        )?;
+        order.token_decimals = ctx.accounts.token_mint.decimals;
+        order.authority_bump = *ctx.bumps.get("order_authority").unwrap();
+        order.order_bump = *ctx.bumps.get("sell_order").unwrap();
 
         // --- delegate SPL tokens to PDA -------------------------------------
-        token::approve(
+        // the allowance is shared by every order on this token account
+        let allowance = delegated_to(
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.order_authority,
+        )
+        .checked_add(params.amount)
+        .ok_or(SellError::MathOverflow)?;
+        approve_delegate(
+            &ctx.accounts.token_program,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.order_authority,
+            &ctx.accounts.seller,
+            allowance,
+        )?;
+        invariants::order_pdas(order, &ctx.accounts.order_authority.key());
+        invariants::covered(
+            order,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.order_authority,
+        );
+
+        emit!(SellOrderCreated {
+            sell_order: order.key(),
+            seller: order.seller,
+            token_mint: order.token_mint,
+            amount: params.amount,
+            price_per_token: params.price_per_token,
+            decimals: ctx.accounts.token_mint.decimals,
+            deadline: params.deadline,
+            payment_mint: params.payment_mint,
+            created_at: order.created_at,
+            label: order.label,
+        });
+        let key = ctx.accounts.sell_order.key();
+        index_add(ctx.accounts.seller_index.as_deref_mut(), key)?;
+        index_add(ctx.accounts.mint_index.as_deref_mut(), key)
+    }
+
+    /// Create a sell order whose tokens are moved into a program‑owned vault
+    /// up front, so the seller cannot pull them out from under buyers.  The
+    /// vault takes the place of the seller’s token account for every other
+    /// instruction.  With `percentage_of_balance` that share of the seller's
+    /// token account is escrowed.
+    pub fn create_escrow_order(ctx: Context<CreateEscrowOrder>, params: OrderParams) -> Result<()> {
+        let mut params = params;
+        require!(!ctx.accounts.config.paused, SellError::ProgramPaused);
+        size_from_balance(&mut params, ctx.accounts.seller_token_account.amount)?;
+        validate_nft(&params, &ctx.accounts.token_mint)?;
+        check_mint_allowed(&ctx.accounts.mint_allowlist, &ctx.accounts.token_mint.key())?;
+
+        // --- persist order data ---------------------------------------------
+        let order = &mut ctx.accounts.sell_order;
+        order.open(
+            &params,
+            ctx.accounts.seller.key(),
+            ctx.accounts.vault.mint,
+            ctx.accounts.vault.key(),
+            true,
+            Clock::get()?.unix_timestamp,
+        )?;
+        order.token_decimals = ctx.accounts.token_mint.decimals;
+        order.authority_bump = *ctx.bumps.get("order_authority").unwrap();
+        order.order_bump = *ctx.bumps.get("sell_order").unwrap();
+        order.vault_bump = *ctx.bumps.get("vault").unwrap();
+        invariants::order_pdas(order, &ctx.accounts.order_authority.key());
+
+        // --- escrow SPL tokens in the vault ---------------------------------
+        token_interface::transfer_checked(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                Approve {
-                    to: ctx.accounts.seller_token_account.to_account_info(),
-                    delegate: ctx.accounts.order_authority.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.seller_token_account.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
                     authority: ctx.accounts.seller.to_account_info(),
                 },
             ),
+            params.amount,
+            ctx.accounts.token_mint.decimals,
+        )?;
+        // transfer‑fee mints deliver less than `amount` to the vault
+        check_escrow_sync(&ctx.accounts.vault, order)?;
+
+        emit!(SellOrderCreated {
+            sell_order: order.key(),
+            seller: order.seller,
+            token_mint: order.token_mint,
+            amount: params.amount,
+            price_per_token: params.price_per_token,
+            decimals: ctx.accounts.token_mint.decimals,
+            deadline: params.deadline,
+            payment_mint: params.payment_mint,
+            created_at: order.created_at,
+            label: order.label,
+        });
+        let key = ctx.accounts.sell_order.key();
+        index_add(ctx.accounts.seller_index.as_deref_mut(), key)?;
+        index_add(ctx.accounts.mint_index.as_deref_mut(), key)
+    }
+
+    /// Anyone can buy `amount` tokens (up to what remains) *before* the
+    /// deadline by paying `amount * price_at(now)` lamports, split between
+    /// the seller (or its `payout` address) and the protocol treasury
+    /// according to `config.fee_bps` (none is taken when the buyer passes
+    /// its `FeeExemption`).  The order account is closed (rent back to
+    /// seller) once fully sold.  Reverts if the price moved above the
+    /// buyer’s `max_price_per_token`.  For Token‑2022 transfer‑fee mints the
+    /// buyer pays for the fee‑inclusive amount and receives exactly `amount`.
+    /// A nonzero `request_id` (which needs the buyer’s `PurchaseRecord`)
+    /// makes a resubmitted transaction fail with `DuplicateRequest`.
+    /// Orders priced in a `payment_mint` can be bought here too at their
+    /// `sol_price_per_token`, if set.  Independently of the per‑token
+    /// check, a total above `max_total_cost` lamports fails with
+    /// `TotalCostExceeded`.
+    ///
+    /// Orders with a `release_delay` hold the seller’s share of each fill
+    /// in a new `PaymentReceipt` (seeded by the order, its `created_at` and
+    /// its `receipts_issued` before the fill, rent paid by the buyer) until
+    /// [`release_payment`].
+    ///
+    /// The fill that brings an escrow order to its `max_fills` returns the
+    /// rest of the vault to `seller_token_account`, required for that fill,
+    /// and closes the order.
+    ///
+    /// Orders with extra `sources` take them as `[token_account, authority]`
+    /// remaining account pairs, see [`add_source`].
+    ///
+    /// Orders with a `callback_program` CPI its `on_fill` entrypoint after
+    /// the fill with a [`FillCallback`], passing the order and buyer token
+    /// account read‑only and `callback_state` writable, none as signers.
+    /// The order account still holds its pre‑fill state at that point,
+    /// unless the fill sold the order out and closed it: the account is
+    /// already closed then, and only the `FillCallback` (with
+    /// `remaining_after` 0) describes the order.
+    /// Without `callback_required` the callback is skipped when
+    /// `callback_program` is not passed; with it, it must be, and the order
+    /// can only be filled through the lamport buys.  A callback that fails
+    /// always reverts the buy, as every failed CPI does.
+    pub fn buy<'info>(
+        ctx: Context<'_, '_, '_, 'info, Buy<'info>>,
+        amount: u64,
+        max_price_per_token: u64,
+        max_total_cost: u64,
+        request_id: Option<u64>,
+    ) -> Result<()> {
+        buy_with_lamports(
+            ctx.accounts,
+            ctx.remaining_accounts,
+            BuySize::Net(amount),
+            max_price_per_token,
+            max_total_cost,
+            request_id,
+        )
+    }
+
+    /// [`buy`] the whole `amount_remaining` in one go.  On transfer‑fee
+    /// mints the buyer receives the remainder net of the fee.  Like every
+    /// fill that clears an order, it skips the dust check and expiry
+    /// reminder, which only concern orders that stay live.
+    pub fn buy_all<'info>(
+        ctx: Context<'_, '_, '_, 'info, Buy<'info>>,
+        max_price_per_token: u64,
+        request_id: Option<u64>,
+    ) -> Result<()> {
+        buy_with_lamports(
+            ctx.accounts,
+            ctx.remaining_accounts,
+            BuySize::All,
+            max_price_per_token,
+            u64::MAX,
+            request_id,
+        )
+    }
+
+    /// Best‑effort [`buy`] of up to `max_amount`: takes
+    /// `min(max_amount, amount_remaining)` instead of failing when the order
+    /// has less left, but still reverts if the price is above
+    /// `max_price_per_token`.  The quantity is what leaves the order; on
+    /// transfer‑fee mints the buyer receives it net of the fee.
+    pub fn buy_up_to<'info>(
+        ctx: Context<'_, '_, '_, 'info, Buy<'info>>,
+        max_amount: u64,
+        max_price_per_token: u64,
+        request_id: Option<u64>,
+    ) -> Result<()> {
+        buy_with_lamports(
+            ctx.accounts,
+            ctx.remaining_accounts,
+            BuySize::UpTo(max_amount),
+            max_price_per_token,
+            u64::MAX,
+            request_id,
+        )
+    }
+
+    /// [`buy`] for program‑controlled buyers (multisigs, vaults) calling in
+    /// through CPI.  The contract with the calling program:
+    ///
+    /// * `buyer` is a PDA of the caller, signed with `invoke_signed` and
+    ///   the caller's own seeds; this program never needs those seeds.
+    /// * `buyer` is owned by the System Program and holds the lamports to
+    ///   pay, since payment is a System Program transfer out of it.  A
+    ///   program‑owned account must first move lamports into such a PDA.
+    /// * `buyer_token_account` is any account of the order's mint the caller
+    ///   controls, typically one owned by the same PDA.
+    ///
+    /// Keys on the ed25519 curve are rejected, so wallets keep using `buy`.
+    pub fn buy_via_cpi<'info>(
+        ctx: Context<'_, '_, '_, 'info, Buy<'info>>,
+        amount: u64,
+        max_price_per_token: u64,
+        request_id: Option<u64>,
+    ) -> Result<()> {
+        require!(
+            !ctx.accounts.buyer.key().is_on_curve(),
+            SellError::BuyerNotPda
+        );
+        buy_with_lamports(
+            ctx.accounts,
+            ctx.remaining_accounts,
+            BuySize::Net(amount),
+            max_price_per_token,
+            u64::MAX,
+            request_id,
+        )
+    }
+
+    /// [`buy`] as many tokens as `max_lamports` pays for at the current
+    /// price, rounding the quantity down so the buyer never pays more.
+    /// Unspent lamports stay with the buyer.  The quantity is what leaves
+    /// the order; on transfer‑fee mints the buyer receives it net of the fee.
+    pub fn buy_exact_cost<'info>(
+        ctx: Context<'_, '_, '_, 'info, Buy<'info>>,
+        max_lamports: u64,
+    ) -> Result<()> {
+        buy_with_lamports(
+            ctx.accounts,
+            ctx.remaining_accounts,
+            BuySize::Budget(max_lamports),
+            u64::MAX,
+            u64::MAX,
+            None,
+        )
+    }
+
+    /// Same as [`buy`] but for orders priced in an SPL token: the buyer pays
+    /// `amount * price_at(now)` of `payment_mint` to the seller.
+    pub fn buy_with_token(
+        ctx: Context<BuyWithToken>,
+        amount: u64,
+        max_price_per_token: u64,
+        request_id: Option<u64>,
+    ) -> Result<()> {
+        require!(
+            !ctx.accounts.sell_order.callback_required,
+            SellError::CallbackRequired
+        );
+        require!(
+            ctx.accounts.sell_order.release_delay == 0,
+            SellError::PaymentsHeld
+        );
+        let payment_mint = ctx
+            .accounts
+            .sell_order
+            .payment_mint
+            .ok_or(SellError::WrongPaymentMethod)?;
+        require!(
+            ctx.accounts.buyer_payment_account.mint == payment_mint
+                && ctx.accounts.seller_payment_account.mint == payment_mint,
+            SellError::PaymentMintMismatch
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let fill = Fill {
+            buyer: ctx.accounts.buyer.key(),
+            seller: ctx.accounts.seller.to_account_info(),
+            sell_order: &mut ctx.accounts.sell_order,
+            token_account: &ctx.accounts.token_account,
+            token_mint: &ctx.accounts.token_mint,
+            buyer_token_account: &ctx.accounts.buyer_token_account,
+            seller_token_account: ctx.accounts.seller_token_account.as_ref(),
+            order_authority: &ctx.accounts.order_authority,
+            whitelist: ctx.accounts.whitelist.as_deref(),
+            fill_feed: ctx.accounts.fill_feed.as_deref_mut(),
+            seller_index: ctx.accounts.seller_index.as_deref_mut(),
+            mint_index: ctx.accounts.mint_index.as_deref_mut(),
+            purchase_record: ctx.accounts.purchase_record.as_deref_mut(),
+            request_id,
+            price_override: None,
+            sources: &[],
+            fee_bps: ctx.accounts.config.fee_bps,
+            config: &ctx.accounts.config,
+            token_program: &ctx.accounts.token_program,
+        };
+        let quote = fill.prepare(amount, max_price_per_token, now)?;
+
+        // --- handle payment --------------------------------------------------
+        transfer_payment_tokens(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.buyer_payment_account,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.seller_payment_account,
+            &ctx.accounts.buyer,
+            quote.proceeds()?,
+        )?;
+        transfer_payment_tokens(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.buyer_payment_account,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.treasury_payment_account,
+            &ctx.accounts.buyer,
+            quote.fee,
+        )?;
+        if quote.royalty > 0 {
+            let creator = ctx
+                .accounts
+                .royalty_payment_account
+                .as_ref()
+                .ok_or(SellError::InvalidRoyalty)?;
+            require!(creator.mint == payment_mint, SellError::PaymentMintMismatch);
+            transfer_payment_tokens(
+                &ctx.accounts.payment_token_program,
+                &ctx.accounts.buyer_payment_account,
+                &ctx.accounts.payment_mint,
+                creator,
+                &ctx.accounts.buyer,
+                quote.royalty,
+            )?;
+        }
+
+        fill.settle(&quote, now)
+    }
+
+    /// Sweep several lamport‑priced orders atomically.  Each leg passes
+    /// `BATCH_LEG_ACCOUNTS` remaining accounts, in order:
+    /// `[sell_order, seller, token_account, token_mint, buyer_token_account,
+    /// order_authority, proceeds]`, with the matching entry of `amounts` and
+    /// `max_prices_per_token`.  Every leg runs the same checks as [`buy`] and
+    /// any failure reverts the whole transaction.  Whitelisted orders are not
+    /// supported.  At most `MAX_BATCH_LEGS` legs fit in one transaction.
+    pub fn buy_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyBatch<'info>>,
+        amounts: Vec<u64>,
+        max_prices_per_token: Vec<u64>,
+    ) -> Result<()> {
+        require!(
+            !amounts.is_empty()
+                && amounts.len() <= MAX_BATCH_LEGS
+                && amounts.len() == max_prices_per_token.len()
+                && ctx.remaining_accounts.len() == amounts.len() * BATCH_LEG_ACCOUNTS,
+            SellError::InvalidBatch
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let legs = ctx.remaining_accounts.chunks(BATCH_LEG_ACCOUNTS);
+        for (leg, (&amount, &max_price_per_token)) in
+            legs.zip(amounts.iter().zip(max_prices_per_token.iter()))
+        {
+            let mut sell_order = Account::<SellOrder>::try_from(&leg[0])?;
+            let seller = &leg[1];
+            let token_account = InterfaceAccount::<TokenAccount>::try_from(&leg[2])?;
+            let token_mint = InterfaceAccount::<Mint>::try_from(&leg[3])?;
+            let buyer_token_account = InterfaceAccount::<TokenAccount>::try_from(&leg[4])?;
+            let order_authority = SystemAccount::try_from(&leg[5])?;
+            let proceeds = &leg[6];
+
+            // --- same constraints `Buy` enforces declaratively ---------------
+            require!(
+                leg[0].is_writable
+                    && leg[2].is_writable
+                    && leg[4].is_writable
+                    && proceeds.is_writable,
+                SellError::InvalidBatch
+            );
+            require_keys_eq!(seller.key(), sell_order.seller, SellError::InvalidBatch);
+            require_keys_eq!(
+                token_account.key(),
+                sell_order.token_account,
+                SellError::InvalidBatch
+            );
+            require_keys_eq!(
+                token_mint.key(),
+                sell_order.token_mint,
+                SellError::InvalidBatch
+            );
+            require_keys_eq!(
+                *leg[3].owner,
+                ctx.accounts.token_program.key(),
+                SellError::WrongTokenProgram
+            );
+            require_keys_eq!(
+                proceeds.key(),
+                sell_order.proceeds_recipient(),
+                SellError::InvalidPayout
+            );
+            require!(
+                sell_order.payment_mint.is_none(),
+                SellError::WrongPaymentMethod
+            );
+            // a leg has no slot for the royalty creator
+            require!(sell_order.royalty_bps == 0, SellError::InvalidBatch);
+            // nor for callback accounts
+            require!(
+                sell_order.callback_program.is_none(),
+                SellError::InvalidBatch
+            );
+            // nor for payment receipts
+            require!(sell_order.release_delay == 0, SellError::InvalidBatch);
+            // nor for the seller account a capped escrow order returns to
+            require!(
+                sell_order.max_fills == 0 || !sell_order.escrowed,
+                SellError::InvalidBatch
+            );
+            let proceeds = if sell_order.escrow_proceeds {
+                &leg[0]
+            } else {
+                proceeds
+            };
+
+            let fill = Fill {
+                buyer: ctx.accounts.buyer.key(),
+                seller: seller.clone(),
+                sell_order: &mut sell_order,
+                token_account: &token_account,
+                token_mint: &token_mint,
+                buyer_token_account: &buyer_token_account,
+                seller_token_account: None,
+                order_authority: &order_authority,
+                whitelist: None,
+                fill_feed: None,
+                seller_index: ctx.accounts.seller_index.as_deref_mut(),
+                mint_index: ctx.accounts.mint_index.as_deref_mut(),
+                purchase_record: None,
+                request_id: None,
+                price_override: None,
+                sources: &[],
+                fee_bps: ctx.accounts.config.fee_bps,
+                config: &ctx.accounts.config,
+                token_program: &ctx.accounts.token_program,
+            };
+            let quote = fill.prepare(amount, max_price_per_token, now)?;
+
+            transfer_lamports(
+                &ctx.accounts.system_program,
+                &ctx.accounts.buyer,
+                proceeds.clone(),
+                quote.proceeds()?,
+            )?;
+            transfer_lamports(
+                &ctx.accounts.system_program,
+                &ctx.accounts.buyer,
+                ctx.accounts.treasury.to_account_info(),
+                quote.fee,
+            )?;
+
+            fill.settle(&quote, now)?;
+
+            // accounts loaded by hand are not persisted by Anchor; skip orders
+            // `settle` already closed
+            if !leg[0].data_is_empty() {
+                sell_order.exit(ctx.program_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Buyer offers `price_per_token` lamports for `amount` tokens of a
+    /// lamport‑priced order, escrowing the full price on a `Bid` PDA until
+    /// the seller accepts or the bidder cancels.
+    pub fn create_bid(ctx: Context<CreateBid>, amount: u64, price_per_token: u64) -> Result<()> {
+        let order = &ctx.accounts.sell_order;
+        require!(order.payment_mint.is_none(), SellError::WrongPaymentMethod);
+        require!(
+            amount > 0 && amount <= order.amount_remaining,
+            SellError::InvalidAmount
+        );
+        require!(
+            price_per_token > 0 && on_tick(price_per_token, order.tick_size),
+            SellError::InvalidPrice
+        );
+        // fees are carved out of the escrow when the bid is accepted
+        let (escrow, ..) = compute_totals(amount, price_per_token, 0, 0)?;
+
+        let bid = &mut ctx.accounts.bid;
+        bid.bidder = ctx.accounts.bidder.key();
+        bid.sell_order = order.key();
+        bid.amount = amount;
+        bid.price_per_token = price_per_token;
+        bid.bump = *ctx.bumps.get("bid").unwrap();
+
+        transfer_lamports(
+            &ctx.accounts.system_program,
+            &ctx.accounts.bidder,
+            ctx.accounts.bid.to_account_info(),
+            escrow,
+        )
+    }
+
+    /// Bidder withdraws their bid; the escrowed price and the rent are
+    /// returned by closing the PDA.
+    pub fn cancel_bid(_ctx: Context<CancelBid>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Seller fills a bid in full at the bidder’s price.  The fee, royalty
+    /// and proceeds are paid out of the bid escrow, which is then closed to
+    /// the bidder.  On transfer‑fee mints the bidder receives `amount` net
+    /// of the fee.
+    pub fn accept_bid(ctx: Context<AcceptBid>) -> Result<()> {
+        require!(
+            !ctx.accounts.sell_order.callback_required,
+            SellError::CallbackRequired
+        );
+        require!(
+            ctx.accounts.sell_order.release_delay == 0,
+            SellError::PaymentsHeld
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let bid = &ctx.accounts.bid;
+        let bid_info = bid.to_account_info();
+
+        // escrowed proceeds stay on the order account until `withdraw_proceeds`
+        let proceeds = if ctx.accounts.sell_order.escrow_proceeds {
+            ctx.accounts.sell_order.to_account_info()
+        } else {
+            ctx.accounts.proceeds.to_account_info()
+        };
+
+        let fill = Fill {
+            buyer: bid.bidder,
+            seller: ctx.accounts.seller.to_account_info(),
+            sell_order: &mut ctx.accounts.sell_order,
+            token_account: &ctx.accounts.token_account,
+            token_mint: &ctx.accounts.token_mint,
+            buyer_token_account: &ctx.accounts.bidder_token_account,
+            seller_token_account: ctx.accounts.seller_token_account.as_ref(),
+            order_authority: &ctx.accounts.order_authority,
+            whitelist: ctx.accounts.whitelist.as_deref(),
+            fill_feed: ctx.accounts.fill_feed.as_deref_mut(),
+            seller_index: ctx.accounts.seller_index.as_deref_mut(),
+            mint_index: ctx.accounts.mint_index.as_deref_mut(),
+            purchase_record: ctx.accounts.purchase_record.as_deref_mut(),
+            request_id: None,
+            price_override: Some(bid.price_per_token),
+            sources: &[],
+            fee_bps: ctx.accounts.config.fee_bps,
+            config: &ctx.accounts.config,
+            token_program: &ctx.accounts.token_program,
+        };
+        let quote = fill.prepare_gross(bid.amount, bid.price_per_token, now)?;
+
+        // --- pay out of the bid escrow -----------------------------------------
+        move_lamports(&bid_info, &proceeds, quote.proceeds()?)?;
+        move_lamports(
+            &bid_info,
+            &ctx.accounts.treasury.to_account_info(),
+            quote.fee,
+        )?;
+        if quote.royalty > 0 {
+            let creator = ctx
+                .accounts
+                .royalty_creator
+                .as_ref()
+                .ok_or(SellError::InvalidRoyalty)?;
+            move_lamports(&bid_info, &creator.to_account_info(), quote.royalty)?;
+        }
+
+        fill.settle(&quote, now)
+    }
+
+    /// Bid `bid` lamports for the whole lot of an `EnglishAuction` order.
+    /// The bid is held on the order account and must beat the current high
+    /// bid (and be at least `amount_remaining × price_per_token`); the
+    /// outbid bidder is refunded in the same instruction.
+    pub fn place_auction_bid(ctx: Context<PlaceAuctionBid>, bid: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let order = &ctx.accounts.sell_order;
+        require!(!ctx.accounts.config.paused, SellError::ProgramPaused);
+        require!(
+            order.pricing_mode == PricingMode::EnglishAuction,
+            SellError::InvalidPricingMode
+        );
+        require!(
+            order.status == OrderStatus::Active,
+            SellError::OrderNotActive
+        );
+        require!(!order.paused, SellError::OrderPaused);
+        require!(now >= order.start_time, SellError::OrderNotStarted);
+        require!(now <= order.deadline, SellError::OrderExpired);
+        let bidder = ctx.accounts.bidder.key();
+        require_keys_neq!(bidder, order.seller, SellError::SelfTrade);
+        order.validate_buyer(&bidder, ctx.accounts.whitelist.as_deref())?;
+        let (opening_bid, ..) =
+            compute_totals(order.amount_remaining, order.price_per_token, 0, 0)?;
+        require!(
+            bid >= opening_bid && bid > order.high_bid,
+            SellError::AuctionBidTooLow
+        );
+
+        transfer_lamports(
+            &ctx.accounts.system_program,
+            &ctx.accounts.bidder,
+            ctx.accounts.sell_order.to_account_info(),
+            bid,
+        )?;
+        if order.high_bidder.is_some() {
+            let previous = ctx
+                .accounts
+                .previous_bidder
+                .as_ref()
+                .ok_or(SellError::InvalidPreviousBidder)?;
+            move_lamports(
+                &ctx.accounts.sell_order.to_account_info(),
+                &previous.to_account_info(),
+                order.high_bid,
+            )?;
+        }
+
+        let order = &mut ctx.accounts.sell_order;
+        order.high_bid = bid;
+        order.high_bidder = Some(bidder);
+        emit!(AuctionBidPlaced {
+            sell_order: order.key(),
+            bidder,
+            bid,
+        });
+        Ok(())
+    }
+
+    /// Anyone settles an `EnglishAuction` after its deadline: the vault goes
+    /// to the high bidder and the high bid is split into proceeds, fee and
+    /// royalty.  An auction without bids is ended with `reclaim`.
+    pub fn settle_auction(ctx: Context<SettleAuction>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let order = &ctx.accounts.sell_order;
+        require!(!ctx.accounts.config.paused, SellError::ProgramPaused);
+        require!(
+            order.pricing_mode == PricingMode::EnglishAuction,
+            SellError::InvalidPricingMode
+        );
+        // a stray vault deposit keeps a settled auction open
+        require!(
+            order.status == OrderStatus::Active,
+            SellError::OrderNotActive
+        );
+        require!(now > order.deadline, SellError::DeadlineNotReached);
+        let winner = order.high_bidder.ok_or(SellError::NoAuctionBids)?;
+        let amount = order.amount_remaining;
+        require!(amount > 0, SellError::InvalidAmount);
+        let (proceeds, fee, royalty) = split_payment(
+            order.high_bid,
+            ctx.accounts.config.fee_bps,
+            order.royalty_bps,
+        )?;
+
+        transfer_order_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.winner_token_account,
+            &ctx.accounts.order_authority,
+            order.authority_seeds(),
             amount,
         )?;
 
+        // --- pay out of the escrowed bid ---------------------------------------
+        let order_info = order.to_account_info();
+        move_lamports(
+            &order_info,
+            &ctx.accounts.proceeds.to_account_info(),
+            proceeds,
+        )?;
+        move_lamports(&order_info, &ctx.accounts.treasury.to_account_info(), fee)?;
+        if royalty > 0 {
+            let creator = ctx
+                .accounts
+                .royalty_creator
+                .as_ref()
+                .ok_or(SellError::InvalidRoyalty)?;
+            move_lamports(&order_info, &creator.to_account_info(), royalty)?;
+        }
+
+        let order = &mut ctx.accounts.sell_order;
+        let price_per_token = order.high_bid / amount;
+        order.amount_remaining = 0;
+        order.total_filled = order
+            .total_filled
+            .checked_add(amount)
+            .ok_or(SellError::MathOverflow)?;
+        order.num_fills = order
+            .num_fills
+            .checked_add(1)
+            .ok_or(SellError::MathOverflow)?;
+        order.accrue_price_time(price_per_token, now)?;
+        order.status = OrderStatus::Filled;
+        emit!(OrderFilled {
+            sell_order: order.key(),
+            seller: order.seller,
+            buyer: winner,
+            token_mint: order.token_mint,
+            amount_filled: amount,
+            price_per_token,
+            price_paid: order.high_bid,
+            remaining_after: 0,
+            maker: order.seller,
+            taker: winner,
+            notional_tokens: amount,
+            notional_payment: order.high_bid,
+            payment_mint: None,
+        });
+
+        index_remove(ctx.accounts.seller_index.as_deref_mut(), &order.key());
+        index_remove(ctx.accounts.mint_index.as_deref_mut(), &order.key());
+        close_sold_out_order(
+            order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.order_authority,
+            ctx.accounts.seller.to_account_info(),
+        )
+    }
+
+    /// Seller temporarily stops fills on a live order, keeping the order,
+    /// its allowance and its rent in place.
+    pub fn pause_order(ctx: Context<UpdateOrder>) -> Result<()> {
+        ctx.accounts.sell_order.paused = true;
+        Ok(())
+    }
+
+    /// Seller lets a paused order fill again.
+    pub fn resume_order(ctx: Context<UpdateOrder>) -> Result<()> {
+        ctx.accounts.sell_order.paused = false;
         Ok(())
     }
 
-    /// Anyone can buy up to the remaining `amount` of tokens *before* the
-    /// deadline by paying `amount * price_per_token` lamports to the seller.
-    pub fn buy(ctx: Context<Buy>, amount: u64) -> Result<()> {
+    /// Seller pushes the deadline of a live order further into the future
+    /// without re-creating it.
+    pub fn extend_deadline(ctx: Context<UpdateOrder>, new_deadline: i64) -> Result<()> {
         let order = &mut ctx.accounts.sell_order;
 
-        // --- checks ----------------------------------------------------------
-        require!(Clock::get()?.unix_timestamp <= order.deadline, SellError::OrderExpired);
-        require!(amount > 0 && amount <= order.amount_remaining, SellError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            new_deadline > order.deadline,
+            SellError::DeadlineNotExtended
+        );
+        require!(new_deadline > now, SellError::DeadlineInPast);
+        // bidders committed against the current close
+        require!(order.high_bidder.is_none(), SellError::AuctionPending);
+        require!(
+            new_deadline <= now.saturating_add(MAX_DURATION),
+            SellError::DeadlineTooFar
+        );
 
-        // --- handle payment --------------------------------------------------
-        let total_price = amount
-            .checked_mul(order.price_per_token)
+        order.deadline = new_deadline;
+        // remind again ahead of the new deadline
+        order.reminder_sent = false;
+        Ok(())
+    }
+
+    /// Seller reprices a live order; later buys are charged the new price.
+    /// Repricing again within `config.price_update_cooldown` seconds fails
+    /// with `PriceUpdateTooSoon`, so prices cannot flicker under pending buys.
+    pub fn update_price(ctx: Context<UpdatePrice>, new_price_per_token: u64) -> Result<()> {
+        require!(new_price_per_token > 0, SellError::InvalidPrice);
+
+        let now = Clock::get()?.unix_timestamp;
+        let order = &mut ctx.accounts.sell_order;
+        require!(
+            order.pricing_mode == PricingMode::Fixed,
+            SellError::InvalidPricingMode
+        );
+        require!(
+            order.last_price_update == 0
+                || now
+                    >= order
+                        .last_price_update
+                        .saturating_add(ctx.accounts.config.price_update_cooldown),
+            SellError::PriceUpdateTooSoon
+        );
+        require!(
+            on_tick(new_price_per_token, order.tick_size),
+            SellError::PriceNotOnTick
+        );
+        let old_price_per_token = order.price_per_token;
+        order.price_per_token = new_price_per_token;
+        order.last_price_update = now;
+
+        emit!(PriceUpdated {
+            sell_order: order.key(),
+            old_price_per_token,
+            new_price_per_token,
+        });
+        Ok(())
+    }
+
+    /// Hand a live order over to `new_seller`, who receives future proceeds
+    /// and may manage or cancel it.  A delegated order keeps selling from its
+    /// token account, so that account must already belong to `new_seller`
+    /// (moving it clears the delegate, which the new seller re‑approves).
+    pub fn transfer_ownership(ctx: Context<TransferOwnership>, new_seller: Pubkey) -> Result<()> {
+        let order = &mut ctx.accounts.sell_order;
+        require!(
+            order.escrowed || ctx.accounts.token_account.owner == new_seller,
+            SellError::InvalidNewSeller
+        );
+
+        order.seller = new_seller;
+        // the previous seller’s payout choice does not carry over
+        order.payout = None;
+        Ok(())
+    }
+
+    /// Seller lowers the quantity for sale while keeping the order open; the
+    /// delegate allowance shrinks by the same amount.
+    pub fn reduce_order(ctx: Context<ResizeOrder>, new_amount: u64) -> Result<()> {
+        let order = &mut ctx.accounts.sell_order;
+        require!(!order.escrowed, SellError::EscrowLocked);
+        order.require_cancellable(Clock::get()?.unix_timestamp)?;
+        require!(
+            new_amount > 0 && new_amount < order.amount_remaining,
+            SellError::InvalidAmount
+        );
+
+        let allowance = delegated_to(&ctx.accounts.token_account, &ctx.accounts.order_authority)
+            .saturating_sub(order.amount_remaining - new_amount);
+        approve_delegate(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.order_authority,
+            &ctx.accounts.seller,
+            allowance,
+        )?;
+
+        order.amount_remaining = new_amount;
+        Ok(())
+    }
+
+    /// Seller tops up a live order with `added_amount` more tokens; the
+    /// delegate allowance grows by the same amount.
+    pub fn increase_order(ctx: Context<ResizeOrder>, added_amount: u64) -> Result<()> {
+        let order = &mut ctx.accounts.sell_order;
+        require!(!order.escrowed, SellError::EscrowLocked);
+        require!(added_amount > 0, SellError::InvalidAmount);
+
+        let new_amount = order
+            .amount_remaining
+            .checked_add(added_amount)
             .ok_or(SellError::MathOverflow)?;
+        let allowance = delegated_to(&ctx.accounts.token_account, &ctx.accounts.order_authority)
+            .checked_add(added_amount)
+            .ok_or(SellError::MathOverflow)?;
+        require!(
+            ctx.accounts.token_account.amount >= allowance,
+            SellError::SellerBalanceInsufficient
+        );
+
+        approve_delegate(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.order_authority,
+            &ctx.accounts.seller,
+            allowance,
+        )?;
 
-        **ctx.accounts.buyer.try_borrow_mut_lamports()? -= total_price;
-        **ctx.accounts.seller.try_borrow_mut_lamports()? += total_price;
+        order.amount_remaining = new_amount;
+        Ok(())
+    }
 
-        // --- transfer tokens -------------------------------------------------
-        let seeds: &[&[&[u8]]] = &[&[
-            order.seller.as_ref(),
-            order.token_account.as_ref(),
-            &[order.bump],
-        ]];
+    /// Seller of an escrow order tops up the vault by `amount`.  The order
+    /// is credited with what actually arrives, so `amount_remaining` keeps
+    /// matching the vault on transfer‑fee mints too.
+    pub fn deposit_more(ctx: Context<DepositMore>, amount: u64) -> Result<()> {
+        let order = &ctx.accounts.sell_order;
+        require!(order.escrowed, SellError::NotEscrowOrder);
+        require!(amount > 0 && !order.is_nft, SellError::InvalidAmount);
+        // bids are for the lot as it was listed
+        require!(
+            order.pricing_mode != PricingMode::EnglishAuction,
+            SellError::InvalidPricingMode
+        );
 
-        token::transfer(
-            CpiContext::new_with_signer(
+        let before = ctx.accounts.token_account.amount;
+        token_interface::transfer_checked(
+            CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                TransferChecked {
                     from: ctx.accounts.seller_token_account.to_account_info(),
-                    to: ctx.accounts.buyer_token_account.to_account_info(),
-                    authority: ctx.accounts.order_authority.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.token_account.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
                 },
-                seeds,
             ),
             amount,
+            ctx.accounts.token_mint.decimals,
+        )?;
+        ctx.accounts.token_account.reload()?;
+        let received = ctx.accounts.token_account.amount.saturating_sub(before);
+
+        let order = &mut ctx.accounts.sell_order;
+        order.amount_remaining = order
+            .amount_remaining
+            .checked_add(received)
+            .ok_or(SellError::MathOverflow)?;
+        check_escrow_sync(&ctx.accounts.token_account, order)
+    }
+
+    /// Seller delegates `amount` more tokens from another of their token
+    /// accounts for the order's mint, for balances spread over several
+    /// accounts.  `buy` drains the order's own account first, then the
+    /// sources in the order they were added.  `cancel` and `reduce_order`
+    /// only release the allowance on the order's own account; the seller
+    /// revokes the sources directly.
+    pub fn add_source(ctx: Context<AddSource>, amount: u64) -> Result<()> {
+        let order = &mut ctx.accounts.sell_order;
+        require!(!order.escrowed, SellError::EscrowLocked);
+        require!(amount > 0, SellError::InvalidAmount);
+        let source = ctx.accounts.source_token_account.key();
+        require!(
+            source != order.token_account
+                && !order.sources.iter().any(|s| s.token_account == source),
+            SellError::InvalidSources
+        );
+        require!(
+            order.sources.len() < MAX_SOURCE_ACCOUNTS,
+            SellError::SourcesFull
+        );
+
+        let new_amount = order
+            .amount_remaining
+            .checked_add(amount)
+            .ok_or(SellError::MathOverflow)?;
+        let allowance = delegated_to(
+            &ctx.accounts.source_token_account,
+            &ctx.accounts.source_authority,
+        )
+        .checked_add(amount)
+        .ok_or(SellError::MathOverflow)?;
+        require!(
+            ctx.accounts.source_token_account.amount >= allowance,
+            SellError::SellerBalanceInsufficient
+        );
+
+        approve_delegate(
+            &ctx.accounts.token_program,
+            &ctx.accounts.source_token_account,
+            &ctx.accounts.source_authority,
+            &ctx.accounts.seller,
+            allowance,
         )?;
 
-        order.amount_remaining -= amount;
+        order.amount_remaining = new_amount;
+        order.sources.push(TokenSource {
+            token_account: source,
+            authority_bump: *ctx.bumps.get("source_authority").unwrap(),
+        });
         Ok(())
     }
 
-    /// Seller can cancel the order *any time* (even before deadline).  All
-    /// remaining tokens stay in the seller’s account and delegate is revoked.
-    pub fn cancel(ctx: Context<Cancel>) -> Result<()> {
-        token::revoke(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Revoke {
-                    source: ctx.accounts.seller_token_account.to_account_info(),
-                    authority: ctx.accounts.seller.to_account_info(),
-                },
-            ),
+    /// Once the deadline (and any grace period) has passed, the seller of an
+    /// escrow order takes the unsold tokens back; the vault and order
+    /// accounts are closed and their rent returned.  A sold out order left
+    /// open by a stray vault deposit can be reclaimed right away.
+    pub fn reclaim(ctx: Context<Reclaim>) -> Result<()> {
+        let order = &ctx.accounts.sell_order;
+        require!(order.escrowed, SellError::NotEscrowOrder);
+        // the high bid is on the order account; it must go through
+        // `settle_auction`
+        require!(
+            order.high_bidder.is_none() || order.status == OrderStatus::Filled,
+            SellError::AuctionPending
+        );
+        // closing the order would hand escrowed proceeds to the seller
+        // rather than the payout address
+        require!(order.proceeds_collected == 0, SellError::ProceedsPending);
+        // a sold out order is only still open when a stray deposit kept its
+        // vault from closing
+        require!(
+            order.amount_remaining == 0 || Clock::get()?.unix_timestamp > order.closes_at(),
+            SellError::DeadlineNotReached
+        );
+
+        // sweep the whole vault (not just `amount_remaining`) so stray
+        // deposits cannot block the close below
+        transfer_order_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.order_authority,
+            order.authority_seeds(),
+            ctx.accounts.token_account.amount,
         )?;
+        close_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.order_authority,
+            ctx.accounts.seller.to_account_info(),
+            order,
+        )?;
+        index_remove(ctx.accounts.seller_index.as_deref_mut(), &order.key());
+        index_remove(ctx.accounts.mint_index.as_deref_mut(), &order.key());
         Ok(())
     }
-}
 
-// ============================================================================
-// Accounts structs
-// ============================================================================
-#[derive(Accounts)]
-pub struct CreateSellOrder<'info> {
-    /// Signer creating the order
-    #[account(mut)]
-    pub seller: Signer<'info>,
+    /// Seller collects proceeds escrowed on the order once it is sold out or
+    /// past its deadline.  A sold out order is closed afterwards.
+    pub fn withdraw_proceeds(ctx: Context<WithdrawProceeds>) -> Result<()> {
+        let order = &mut ctx.accounts.sell_order;
+        require!(order.escrow_proceeds, SellError::ProceedsNotEscrowed);
+        require!(
+            order.amount_remaining == 0 || Clock::get()?.unix_timestamp > order.deadline,
+            SellError::ProceedsLocked
+        );
 
-    /// Seller’s SPL token account holding the tokens for sale
-    #[account(mut, owner = token_program.key())]
-    pub seller_token_account: Account<'info, TokenAccount>,
+        let amount = order.proceeds_collected;
+        order.proceeds_collected = 0;
+        move_lamports(
+            &order.to_account_info(),
+            &ctx.accounts.proceeds.to_account_info(),
+            amount,
+        )?;
 
-    /// PDA that becomes the *delegate/authority* for token transfers
-    #[account(
-        seeds = [seller.key().as_ref(), seller_token_account.key().as_ref()],
-        bump,
-    )]
-    pub order_authority: SystemAccount<'info>,
+        // a vault kept open by a stray deposit goes through `reclaim`,
+        // which closes the order along with it
+        let vault_open = order.escrowed && {
+            let vault = ctx
+                .accounts
+                .vault
+                .as_ref()
+                .ok_or(SellError::VaultRequired)?;
+            vault.lamports() > 0
+        };
+        if order.amount_remaining == 0 && !vault_open {
+            order.close(ctx.accounts.seller.to_account_info())?;
+            index_remove(ctx.accounts.seller_index.as_deref_mut(), &order.key());
+            index_remove(ctx.accounts.mint_index.as_deref_mut(), &order.key());
+        }
+        Ok(())
+    }
 
-    /// Order state account (PDA)
-    #[account(
-        init,
-        payer = seller,
-        space = 8 + SellOrder::SIZE,
-        seeds = [b"sell_order", seller.key().as_ref(), seller_token_account.key().as_ref()],
-        bump,
-    )]
-    pub sell_order: Account<'info, SellOrder>,
+    /// Pay a held fill out to the seller (or its `payout` address) and close
+    /// the receipt, returning its rent to the buyer.  The buyer may release
+    /// any time to confirm delivery; anyone may after `release_at`.
+    pub fn release_payment(ctx: Context<ReleasePayment>) -> Result<()> {
+        let receipt = &ctx.accounts.payment_receipt;
+        require!(
+            ctx.accounts.caller.key() == receipt.buyer
+                || Clock::get()?.unix_timestamp >= receipt.release_at,
+            SellError::PaymentLocked
+        );
 
-    /// Programs & sysvars
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
-}
+        move_lamports(
+            &receipt.to_account_info(),
+            &ctx.accounts.payee.to_account_info(),
+            receipt.amount,
+        )?;
+        emit!(PaymentReleased {
+            sell_order: receipt.sell_order,
+            buyer: receipt.buyer,
+            payee: receipt.payee,
+            amount: receipt.amount,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct Buy<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>,
+    /// Seller recovers tokens sent by mistake to a token account owned by
+    /// the order authority PDA, of any mint.  From the order’s own escrow
+    /// vault only the balance above `amount_remaining` can be taken.
+    pub fn rescue_tokens(ctx: Context<RescueTokens>) -> Result<()> {
+        let order = &ctx.accounts.sell_order;
+        let stray = &ctx.accounts.stray_token_account;
+        let amount = if stray.key() == order.token_account {
+            stray.amount.saturating_sub(order.amount_remaining)
+        } else {
+            stray.amount
+        };
+        require!(amount > 0, SellError::InvalidAmount);
 
-    /// Seller receives payment
-    #[account(mut)]
-    pub seller: SystemAccount<'info>,
+        transfer_order_tokens(
+            &ctx.accounts.token_program,
+            stray,
+            &ctx.accounts.mint,
+            &ctx.accounts.destination,
+            &ctx.accounts.order_authority,
+            order.authority_seeds(),
+            amount,
+        )
+    }
 
-    /// Order state
-    #[account(
-        mut,
-        has_one = seller,
-        has_one = token_account,
-        seeds = [b"sell_order", seller.key().as_ref(), token_account.key().as_ref()],
-        bump = sell_order.bump,
-    )]
-    pub sell_order: Account<'info, SellOrder>,
+    /// Seller restricts the order to buyers listed in a `Whitelist` PDA.
+    pub fn init_whitelist(ctx: Context<InitWhitelist>) -> Result<()> {
+        let whitelist = &mut ctx.accounts.whitelist;
+        whitelist.sell_order = ctx.accounts.sell_order.key();
+        whitelist.buyers = Vec::new();
+        whitelist.bump = *ctx.bumps.get("whitelist").unwrap();
 
-    /// Same token account as recorded in the order
-    #[account(mut)]
-    pub token_account: Account<'info, TokenAccount>,
+        ctx.accounts.sell_order.whitelisted = true;
+        Ok(())
+    }
 
-    /// Buyer’s token account to receive tokens
-    #[account(mut)]
-    pub buyer_token_account: Account<'info, TokenAccount>,
+    /// Seller opens a `FillFeed` keeping the order’s last `FILL_FEED_LEN`
+    /// fills on chain.  From then on every fill must pass the feed.
+    pub fn init_fill_feed(ctx: Context<InitFillFeed>) -> Result<()> {
+        let feed = &mut ctx.accounts.fill_feed;
+        feed.sell_order = ctx.accounts.sell_order.key();
+        feed.fills = Vec::new();
+        feed.next = 0;
+        feed.bump = *ctx.bumps.get("fill_feed").unwrap();
 
-    /// PDA delegate that actually moves tokens
-    #[account(
-        seeds = [seller.key().as_ref(), token_account.key().as_ref()],
-        bump = sell_order.bump,
-    )]
-    pub order_authority: SystemAccount<'info>,
+        ctx.accounts.sell_order.records_fills = true;
+        Ok(())
+    }
 
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
+    /// Seller adds `buyers` to the order whitelist.
+    pub fn add_to_whitelist(ctx: Context<UpdateWhitelist>, buyers: Vec<Pubkey>) -> Result<()> {
+        let whitelist = &mut ctx.accounts.whitelist;
+        for buyer in buyers {
+            if !whitelist.buyers.contains(&buyer) {
+                whitelist.buyers.push(buyer);
+            }
+        }
+        require!(
+            whitelist.buyers.len() <= MAX_WHITELIST,
+            SellError::WhitelistFull
+        );
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct Cancel<'info> {
-    #[account(mut)]
-    pub seller: Signer<'info>,
+    /// Buyer opens the `PurchaseRecord` that tracks their cumulative fills
+    /// on capped orders and their last `request_id`.
+    pub fn init_purchase_record(ctx: Context<InitPurchaseRecord>) -> Result<()> {
+        let record = &mut ctx.accounts.purchase_record;
+        record.sell_order = ctx.accounts.sell_order.key();
+        record.buyer = ctx.accounts.buyer.key();
+        record.purchased = 0;
+        record.bump = *ctx.bumps.get("purchase_record").unwrap();
+        record.last_request_id = 0;
+        Ok(())
+    }
 
-    #[account(
-        mut,
-        close = seller,
-        has_one = seller,
-        has_one = token_account,
-        seeds = [b"sell_order", seller.key().as_ref(), token_account.key().as_ref()],
-        bump = sell_order.bump,
-    )]
-    pub sell_order: Account<'info, SellOrder>,
+    /// Seller opens a `SellerIndex` listing their live orders.  Orders are
+    /// added and removed whenever the index is passed to an instruction
+    /// that opens or closes one.
+    pub fn init_seller_index(ctx: Context<InitSellerIndex>) -> Result<()> {
+        let index = &mut ctx.accounts.seller_index;
+        index.seller = ctx.accounts.seller.key();
+        index.orders = Vec::new();
+        index.bump = *ctx.bumps.get("seller_index").unwrap();
+        Ok(())
+    }
 
-    #[account(mut)]
-    pub token_account: Account<'info, TokenAccount>,
+    /// Permissionless: drop index entries for the orders passed as remaining
+    /// accounts that were closed without the index passed or handed to
+    /// another seller.
+    pub fn prune_seller_index(ctx: Context<PruneSellerIndex>) -> Result<()> {
+        let index = &mut ctx.accounts.seller_index;
+        for info in ctx.remaining_accounts {
+            let live = info.owner == &crate::ID
+                && Account::<SellOrder>::try_from(info)
+                    .is_ok_and(|order| order.seller == index.seller);
+            if !live {
+                index.orders.retain(|order| order != info.key);
+            }
+        }
+        Ok(())
+    }
 
-    pub token_program: Program<'info, Token>,
-}
+    /// Anyone creates the order index of `token_mint`, so clients can list
+    /// its orders from one account.  Orders opened before it existed are not
+    /// in it.
+    pub fn init_mint_index(ctx: Context<InitMintIndex>) -> Result<()> {
+        let index = &mut ctx.accounts.mint_index;
+        index.mint = ctx.accounts.token_mint.key();
+        index.orders = Vec::new();
+        index.bump = *ctx.bumps.get("mint_index").unwrap();
+        Ok(())
+    }
 
-// ============================================================================
-// State
-// ============================================================================
-#[account]
-pub struct SellOrder {
-    pub seller: Pubkey,
-    pub token_mint: Pubkey,
-    pub token_account: Pubkey,
-    pub amount_remaining: u64,
-    pub price_per_token: u64,
-    pub deadline: i64,
-    pub bump: u8,
-}
+    /// Permissionless: drop mint index entries for the orders passed as
+    /// remaining accounts that can no longer be bought: closed, replaced by
+    /// an order on another mint, no longer active, at their fill cap or past
+    /// their close.
+    pub fn prune_mint_index(ctx: Context<PruneMintIndex>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let index = &mut ctx.accounts.mint_index;
+        for info in ctx.remaining_accounts {
+            let live = info.owner == &crate::ID
+                && Account::<SellOrder>::try_from(info).is_ok_and(|order| {
+                    order.token_mint == index.mint
+                        && order.status == OrderStatus::Active
+                        && !order.fills_capped()
+                        && now <= order.closes_at()
+                });
+            if !live {
+                index.orders.retain(|order| order != info.key);
+            }
+        }
+        Ok(())
+    }
 
-impl SellOrder {
-    // account discriminator (8) + 32*3 + 8*3 + 1 = 8 + 96 + 24 + 1 = 129
-    pub const SIZE: usize = 129;
-}
+    /// Permissionless check for orders future buys will fail on: emits
+    /// `OrderUnderfunded` if the delegate allowance or the token balance no
+    /// longer covers `amount_remaining`.  The allowance is shared by every
+    /// order on the token account, so this only catches the worst cases.
+    /// Inside the order’s reminder window it also emits `OrderExpiringSoon`.
+    pub fn health_check(ctx: Context<HealthCheck>) -> Result<()> {
+        let key = ctx.accounts.sell_order.key();
+        ctx.accounts
+            .sell_order
+            .remind(key, Clock::get()?.unix_timestamp);
 
-// ============================================================================
-// Errors
-// ============================================================================
-#[error_code]
-pub enum SellError {
+        let order = &ctx.accounts.sell_order;
+        let delegated = if order.escrowed {
+            order.amount_remaining
+        } else {
+            delegated_to(&ctx.accounts.token_account, &ctx.accounts.order_authority)
+        };
+        let balance = ctx.accounts.token_account.amount;
+        if delegated < order.amount_remaining || balance < order.amount_remaining {
+            emit!(OrderUnderfunded {
+                sell_order: order.key(),
+                seller: order.seller,
+                amount_remaining: order.amount_remaining,
+                delegated_amount: delegated,
+                balance,
+            });
+        }
+        Ok(())
+    }
+
+    /// Read‑only: the order’s per‑token price right now.  Meant to be called
+    /// through transaction simulation; the price is returned as return data.
+    pub fn get_current_price(ctx: Context<ViewOrder>) -> Result<u64> {
+        ctx.accounts
+            .sell_order
+            .price_at(Clock::get()?.unix_timestamp)
+    }
+
+    /// Read‑only: remaining amount, time left and status, for countdowns.
+    /// Called through simulation like `get_current_price`.
+    pub fn get_status(ctx: Context<ViewOrder>) -> Result<OrderStatusView> {
+        let order = &ctx.accounts.sell_order;
+        let now = Clock::get()?.unix_timestamp;
+        Ok(OrderStatusView {
+            amount_remaining: order.amount_remaining,
+            seconds_until_deadline: order.deadline.saturating_sub(now).max(0) as u64,
+            expired: now > order.closes_at(),
+            status: order.status,
+        })
+    }
+
+    /// Move an order opened in layout v1, before orders were seeded by
+    /// `order_id`, to its `order_id` address in the current layout.  The
+    /// seller’s delegation and the order authority carry over unchanged.
+    /// The payer covers the rent of the new account; the old account is
+    /// closed to the seller.
+    pub fn migrate_order(ctx: Context<MigrateOrder>, order_id: u64) -> Result<()> {
+        let info = ctx.accounts.legacy_order.to_account_info();
+
+        // the allocated size identifies the layout an order was opened with
+        let legacy = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == SellOrder::discriminator(),
+                SellError::InvalidOrderLayout
+            );
+            require!(
+                data.len() != 8 + SellOrder::SIZE,
+                SellError::AlreadyMigrated
+            );
+            require!(
+                data.len() == 8 + SellOrderV1::SIZE,
+                SellError::InvalidOrderLayout
+            );
+            SellOrderV1::deserialize(&mut &data[8..])?
+        };
+        require_keys_eq!(
+            ctx.accounts.token_mint.key(),
+            legacy.token_mint,
+            SellError::MintMismatch
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let order = &mut ctx.accounts.sell_order;
+        order.set_inner(SellOrder::from(legacy));
+        order.order_id = order_id;
+        order.token_decimals = ctx.accounts.token_mint.decimals;
+        order.order_bump = *ctx.bumps.get("sell_order").unwrap();
+        order.created_at = now;
+        order.last_fill_time = now;
+        invariants::order_pdas(order, &order.authority_address()?);
+
+        // --- close the v1 account -------------------------------------------
+        let seller = ctx.accounts.seller.to_account_info();
+        move_lamports(&info, &seller, info.lamports())?;
+        info.assign(&system_program::ID);
+        info.realloc(0, false)?;
+        Ok(())
+    }
+
+    /// One‑time setup of the global program config.  The signer becomes the
+    /// admin.
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        treasury: Pubkey,
+        fee_bps: u16,
+    ) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, SellError::InvalidFee);
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.treasury = treasury;
+        config.fee_bps = fee_bps;
+        config.paused = false;
+        config.bump = *ctx.bumps.get("config").unwrap();
+        config.referral_bps = 0;
+        config.price_update_cooldown = 0;
+        Ok(())
+    }
+
+    /// Admin sets the share of the protocol fee paid to referrers.
+    pub fn set_referral_bps(ctx: Context<AdminConfig>, referral_bps: u16) -> Result<()> {
+        require!(referral_bps <= MAX_FEE_BPS, SellError::InvalidFee);
+        ctx.accounts.config.referral_bps = referral_bps;
+        Ok(())
+    }
+
+    /// Admin sets the minimum seconds between two `update_price` calls on
+    /// an order; 0 disables the cooldown.
+    pub fn set_price_update_cooldown(ctx: Context<AdminConfig>, cooldown: i64) -> Result<()> {
+        require!(
+            (0..=MAX_DURATION).contains(&cooldown),
+            SellError::InvalidCooldown
+        );
+        ctx.accounts.config.price_update_cooldown = cooldown;
+        Ok(())
+    }
+
+    /// Admin emergency switch.  While paused no orders can be created or
+    /// filled, but sellers can still `cancel`.
+    pub fn set_paused(ctx: Context<AdminConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+        Ok(())
+    }
+
+    /// Admin restricts listings to an allowlist of mints.  Without it any
+    /// mint can be sold.
+    pub fn init_mint_allowlist(ctx: Context<InitMintAllowlist>) -> Result<()> {
+        let allowlist = &mut ctx.accounts.mint_allowlist;
+        allowlist.mints = Vec::new();
+        allowlist.bump = *ctx.bumps.get("mint_allowlist").unwrap();
+        Ok(())
+    }
+
+    pub fn add_mint(ctx: Context<UpdateMintAllowlist>, mint: Pubkey) -> Result<()> {
+        let allowlist = &mut ctx.accounts.mint_allowlist;
+        if !allowlist.mints.contains(&mint) {
+            allowlist.mints.push(mint);
+        }
+        require!(
+            allowlist.mints.len() <= MAX_ALLOWED_MINTS,
+            SellError::MintAllowlistFull
+        );
+        Ok(())
+    }
+
+    /// Live orders for a removed mint are unaffected; only new listings are
+    /// refused.
+    pub fn remove_mint(ctx: Context<UpdateMintAllowlist>, mint: Pubkey) -> Result<()> {
+        ctx.accounts.mint_allowlist.mints.retain(|m| *m != mint);
+        Ok(())
+    }
+
+    /// Admin waives the protocol fee on `buyer`'s lamport buys (market
+    /// makers, partners).  The buyer passes its `FeeExemption` to `buy`.
+    pub fn grant_exemption(ctx: Context<GrantExemption>, buyer: Pubkey) -> Result<()> {
+        let exemption = &mut ctx.accounts.fee_exemption;
+        exemption.buyer = buyer;
+        exemption.bump = *ctx.bumps.get("fee_exemption").unwrap();
+        Ok(())
+    }
+
+    /// Admin withdraws a fee exemption, reclaiming its rent.
+    pub fn revoke_exemption(_ctx: Context<RevokeExemption>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Anyone may close a delegated order once it can no longer be filled,
+    /// returning the rent to the seller.  Only the token owner can revoke the
+    /// delegate, so the order’s share of the allowance is left behind; with
+    /// the order gone the program never signs for it.
+    pub fn close_expired(ctx: Context<CloseExpired>) -> Result<()> {
+        let order = &ctx.accounts.sell_order;
+        require!(!order.escrowed, SellError::EscrowLocked);
+        require!(order.proceeds_collected == 0, SellError::ProceedsPending);
+        require!(
+            Clock::get()?.unix_timestamp > order.closes_at(),
+            SellError::DeadlineNotReached
+        );
+
+        emit!(OrderCancelled {
+            sell_order: order.key(),
+            seller: order.seller,
+            token_mint: order.token_mint,
+            remaining: order.amount_remaining,
+        });
+        index_remove(ctx.accounts.seller_index.as_deref_mut(), &order.key());
+        index_remove(ctx.accounts.mint_index.as_deref_mut(), &order.key());
+        Ok(())
+    }
+
+    /// Seller can cancel the order *any time* (even before deadline).  All
+    /// remaining tokens stay in the seller’s account; the order’s share of
+    /// the delegate allowance is withdrawn, revoking it once no other order
+    /// on the token account needs it.
+    ///
+    /// The order’s `cancel_authority` may cancel too.  Changing the
+    /// allowance needs the token owner’s signature, so an operator cancel
+    /// leaves it for the seller to revoke.
+    pub fn cancel(ctx: Context<Cancel>) -> Result<()> {
+        require!(!ctx.accounts.sell_order.escrowed, SellError::EscrowLocked);
+        ctx.accounts
+            .sell_order
+            .require_cancellable(Clock::get()?.unix_timestamp)?;
+        require!(
+            ctx.accounts.sell_order.proceeds_collected == 0,
+            SellError::ProceedsPending
+        );
+
+        if ctx.accounts.authority.key() == ctx.accounts.seller.key() {
+            release_allowance(
+                &ctx.accounts.token_program,
+                &ctx.accounts.token_account,
+                &ctx.accounts.order_authority,
+                &ctx.accounts.authority,
+                ctx.accounts.sell_order.amount_remaining,
+            )?;
+        }
+
+        let order = &ctx.accounts.sell_order;
+        emit!(OrderCancelled {
+            sell_order: order.key(),
+            seller: order.seller,
+            token_mint: order.token_mint,
+            remaining: order.amount_remaining,
+        });
+        index_remove(ctx.accounts.seller_index.as_deref_mut(), &order.key());
+        index_remove(ctx.accounts.mint_index.as_deref_mut(), &order.key());
+        Ok(())
+    }
+
+    /// Cancel up to `MAX_CANCEL_ALL` delegated orders at once.  Each order
+    /// passes `CANCEL_ALL_ACCOUNTS` remaining accounts, in order:
+    /// `[sell_order, token_account, order_authority]`.  Orders that belong
+    /// to another seller, or that `cancel` would refuse (escrowed, holding
+    /// proceeds, locked until the deadline, inactive), are skipped.
+    pub fn cancel_all<'info>(ctx: Context<'_, '_, '_, 'info, CancelAll<'info>>) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        require!(
+            !accounts.is_empty()
+                && accounts.len() % CANCEL_ALL_ACCOUNTS == 0
+                && accounts.len() / CANCEL_ALL_ACCOUNTS <= MAX_CANCEL_ALL,
+            SellError::InvalidBatch
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        for leg in accounts.chunks(CANCEL_ALL_ACCOUNTS) {
+            let order = Account::<SellOrder>::try_from(&leg[0])?;
+            let cancellable = order.seller == ctx.accounts.seller.key()
+                && order.status == OrderStatus::Active
+                && !order.escrowed
+                && order.proceeds_collected == 0
+                && order.require_cancellable(now).is_ok();
+            if !cancellable {
+                continue;
+            }
+
+            let token_account = InterfaceAccount::<TokenAccount>::try_from(&leg[1])?;
+            let order_authority = SystemAccount::try_from(&leg[2])?;
+            require!(
+                leg[0].is_writable && leg[1].is_writable,
+                SellError::InvalidBatch
+            );
+            require_keys_eq!(
+                token_account.key(),
+                order.token_account,
+                SellError::InvalidBatch
+            );
+            require_keys_eq!(
+                order_authority.key(),
+                order.authority_address()?,
+                SellError::InvalidAuthority
+            );
+
+            release_allowance(
+                &ctx.accounts.token_program,
+                &token_account,
+                &order_authority,
+                &ctx.accounts.seller,
+                order.amount_remaining,
+            )?;
+            emit!(OrderCancelled {
+                sell_order: order.key(),
+                seller: order.seller,
+                token_mint: order.token_mint,
+                remaining: order.amount_remaining,
+            });
+            index_remove(ctx.accounts.seller_index.as_deref_mut(), &order.key());
+            index_remove_listed(ctx.accounts.mint_index.as_deref_mut(), &order);
+            order.close(ctx.accounts.seller.to_account_info())?;
+        }
+        Ok(())
+    }
+
+    /// Cancel a delegated order and open a new one in its place, so the
+    /// seller can move to another token account or mint without a window
+    /// where neither order is live.  The new order must live at a different
+    /// PDA (a fresh `order_id` when keeping the token account).
+    pub fn cancel_and_recreate(ctx: Context<CancelAndRecreate>, params: OrderParams) -> Result<()> {
+        require!(!ctx.accounts.config.paused, SellError::ProgramPaused);
+        require!(
+            params.percentage_of_balance.is_none(),
+            SellError::InvalidPercentage
+        );
+        require!(!ctx.accounts.old_order.escrowed, SellError::EscrowLocked);
+        ctx.accounts
+            .old_order
+            .require_cancellable(Clock::get()?.unix_timestamp)?;
+        require!(
+            ctx.accounts.old_order.proceeds_collected == 0,
+            SellError::ProceedsPending
+        );
+        require_keys_neq!(
+            ctx.accounts.old_order.key(),
+            ctx.accounts.new_order.key(),
+            SellError::OrderAlreadyExists
+        );
+        validate_nft(&params, &ctx.accounts.token_mint)?;
+        check_mint_allowed(&ctx.accounts.mint_allowlist, &ctx.accounts.token_mint.key())?;
+
+        // --- wind down the old order ------------------------------------------
+        release_allowance(
+            &ctx.accounts.token_program,
+            &ctx.accounts.old_token_account,
+            &ctx.accounts.old_authority,
+            &ctx.accounts.seller,
+            ctx.accounts.old_order.amount_remaining,
+        )?;
+        let old = &ctx.accounts.old_order;
+        emit!(OrderCancelled {
+            sell_order: old.key(),
+            seller: old.seller,
+            token_mint: old.token_mint,
+            remaining: old.amount_remaining,
+        });
+
+        // --- open the new one -------------------------------------------------
+        let order = &mut ctx.accounts.new_order;
+        order.open(
+            &params,
+            ctx.accounts.seller.key(),
+            ctx.accounts.new_token_account.mint,
+            ctx.accounts.new_token_account.key(),
+            false,
+            Clock::get()?.unix_timestamp,
+        )?;
+        order.token_decimals = ctx.accounts.token_mint.decimals;
+        order.authority_bump = *ctx.bumps.get("new_authority").unwrap();
+        order.order_bump = *ctx.bumps.get("new_order").unwrap();
+        // recreating must not dodge the repricing cooldown
+        order.last_price_update = ctx.accounts.old_order.last_price_update;
+
+        // the old and new token accounts may be the same account
+        ctx.accounts.new_token_account.reload()?;
+        let allowance = delegated_to(&ctx.accounts.new_token_account, &ctx.accounts.new_authority)
+            .checked_add(params.amount)
+            .ok_or(SellError::MathOverflow)?;
+        approve_delegate(
+            &ctx.accounts.token_program,
+            &ctx.accounts.new_token_account,
+            &ctx.accounts.new_authority,
+            &ctx.accounts.seller,
+            allowance,
+        )?;
+
+        let order = &ctx.accounts.new_order;
+        invariants::order_pdas(order, &ctx.accounts.new_authority.key());
+        invariants::covered(
+            order,
+            &ctx.accounts.new_token_account,
+            &ctx.accounts.new_authority,
+        );
+        emit!(SellOrderCreated {
+            sell_order: order.key(),
+            seller: order.seller,
+            token_mint: order.token_mint,
+            amount: params.amount,
+            price_per_token: params.price_per_token,
+            decimals: ctx.accounts.token_mint.decimals,
+            deadline: params.deadline,
+            payment_mint: params.payment_mint,
+            created_at: order.created_at,
+            label: order.label,
+        });
+        let index = ctx.accounts.seller_index.as_deref_mut();
+        index_add(
+            index_remove(index, &ctx.accounts.old_order.key()),
+            ctx.accounts.new_order.key(),
+        )?;
+        // an old order on another mint is left for `prune_mint_index`
+        let index = ctx.accounts.mint_index.as_deref_mut();
+        index_add(
+            index_remove(index, &ctx.accounts.old_order.key()),
+            ctx.accounts.new_order.key(),
+        )
+    }
+
+    /// Replace the terms of a live delegated order in place.  The order PDA
+    /// and its rent are kept instead of closing the account and opening it
+    /// again, which Anchor cannot do within one transaction.  `params` must
+    /// keep the order's `order_id`; fill history restarts with the new
+    /// terms.  The whitelist, fill feed and extra sources set up for the
+    /// order stay attached, as their PDAs can only be initialized once.
+    pub fn relist(ctx: Context<Relist>, params: OrderParams) -> Result<()> {
+        require!(!ctx.accounts.config.paused, SellError::ProgramPaused);
+        require!(
+            params.percentage_of_balance.is_none(),
+            SellError::InvalidPercentage
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let order = &ctx.accounts.sell_order;
+        require!(!order.escrowed, SellError::EscrowLocked);
+        order.require_cancellable(now)?;
+        require!(order.proceeds_collected == 0, SellError::ProceedsPending);
+        require!(
+            params.order_id == order.order_id,
+            SellError::OrderIdMismatch
+        );
+        validate_nft(&params, &ctx.accounts.token_mint)?;
+        check_mint_allowed(&ctx.accounts.mint_allowlist, &ctx.accounts.token_mint.key())?;
+
+        // --- wind down the old terms ------------------------------------------
+        release_allowance(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.order_authority,
+            &ctx.accounts.seller,
+            order.amount_remaining,
+        )?;
+        emit!(OrderCancelled {
+            sell_order: order.key(),
+            seller: order.seller,
+            token_mint: order.token_mint,
+            remaining: order.amount_remaining,
+        });
+
+        // --- reopen the same account ------------------------------------------
+        let order = &mut ctx.accounts.sell_order;
+        let (seller, creator) = (order.seller, order.creator);
+        let (token_mint, token_account) = (order.token_mint, order.token_account);
+        let (whitelisted, records_fills) = (order.whitelisted, order.records_fills);
+        let sources = std::mem::take(&mut order.sources);
+        // `open` refuses accounts that still hold a live order
+        order.seller = Pubkey::default();
+        order.open(&params, seller, token_mint, token_account, false, now)?;
+        // the PDAs stay derived from the original creator
+        order.creator = creator;
+        order.whitelisted = whitelisted;
+        order.records_fills = records_fills;
+        order.sources = sources;
+        order.token_decimals = ctx.accounts.token_mint.decimals;
+
+        ctx.accounts.token_account.reload()?;
+        let allowance = delegated_to(&ctx.accounts.token_account, &ctx.accounts.order_authority)
+            .checked_add(params.amount)
+            .ok_or(SellError::MathOverflow)?;
+        approve_delegate(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_account,
+            &ctx.accounts.order_authority,
+            &ctx.accounts.seller,
+            allowance,
+        )?;
+
+        let order = &ctx.accounts.sell_order;
+        invariants::order_pdas(order, &ctx.accounts.order_authority.key());
+        invariants::covered(
+            order,
+            &ctx.accounts.token_account,
+            &ctx.accounts.order_authority,
+        );
+        emit!(SellOrderCreated {
+            sell_order: order.key(),
+            seller: order.seller,
+            token_mint: order.token_mint,
+            amount: params.amount,
+            price_per_token: params.price_per_token,
+            decimals: ctx.accounts.token_mint.decimals,
+            deadline: params.deadline,
+            payment_mint: params.payment_mint,
+            created_at: order.created_at,
+            label: order.label,
+        });
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Accounts structs
+// ============================================================================
+#[derive(Accounts)]
+#[instruction(params: OrderParams)]
+pub struct CreateSellOrder<'info> {
+    /// Signer creating the order
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// Seller’s SPL token account holding the tokens for sale
+    #[account(mut, owner = token_program.key() @ SellError::WrongTokenProgram)]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Mint of the tokens for sale; its decimals are recorded on the order
+    #[account(
+        address = seller_token_account.mint @ SellError::MintMismatch,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// PDA that becomes the *delegate/authority* for token transfers
+    #[account(
+        seeds = [seller.key().as_ref(), seller_token_account.key().as_ref()],
+        bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Admin mint allowlist; when it has been created only listed mints
+    /// can be sold
+    /// CHECK: may not exist yet, see `check_mint_allowed`
+    #[account(seeds = [b"mint_allowlist"], bump)]
+    pub mint_allowlist: UncheckedAccount<'info>,
+
+    /// Order state account (PDA); `init_if_needed` so a live order at the
+    /// same address is reported as `OrderAlreadyExists` by `open`
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellOrder::SIZE,
+        seeds = [
+            b"sell_order",
+            seller.key().as_ref(),
+            seller_token_account.key().as_ref(),
+            params.order_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", token_mint.key().as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    /// Programs & sysvars
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: OrderParams)]
+pub struct CreateEscrowOrder<'info> {
+    /// Signer creating the order
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// Seller’s SPL token account the escrowed tokens are taken from
+    #[account(mut, token::authority = seller)]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = seller_token_account.mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// PDA that owns the vault
+    #[account(
+        seeds = [seller.key().as_ref(), vault.key().as_ref()],
+        bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    /// Program‑owned token account holding the tokens for sale
+    #[account(
+        init_if_needed,
+        payer = seller,
+        seeds = [
+            b"vault",
+            seller.key().as_ref(),
+            token_mint.key().as_ref(),
+            params.order_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        token::mint = token_mint,
+        token::authority = order_authority,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Admin mint allowlist; when it has been created only listed mints
+    /// can be sold
+    /// CHECK: may not exist yet, see `check_mint_allowed`
+    #[account(seeds = [b"mint_allowlist"], bump)]
+    pub mint_allowlist: UncheckedAccount<'info>,
+
+    /// Order state account (PDA); `init_if_needed` so a live order at the
+    /// same address is reported as `OrderAlreadyExists` by `open`
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellOrder::SIZE,
+        seeds = [
+            b"sell_order",
+            seller.key().as_ref(),
+            vault.key().as_ref(),
+            params.order_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", token_mint.key().as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    /// Programs & sysvars
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Buy<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// Seller receives the rent once the order is fully sold
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+
+    /// Order state
+    #[account(
+        mut,
+        has_one = seller,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Same token account as recorded in the order
+    #[account(mut)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = sell_order.token_mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// Buyer’s token account to receive tokens
+    #[account(mut)]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller’s account receiving the rest of an escrow order’s vault; only
+    /// needed for the fill that reaches its `max_fills`
+    #[account(mut, token::mint = sell_order.token_mint, token::authority = seller)]
+    pub seller_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// PDA delegate that actually moves tokens; checked against the
+    /// order’s stored bump with a dedicated `InvalidAuthority` error
+    pub order_authority: SystemAccount<'info>,
+
+    /// Required when the order is whitelisted
+    #[account(seeds = [b"whitelist", sell_order.key().as_ref()], bump = whitelist.bump)]
+    pub whitelist: Option<Account<'info, Whitelist>>,
+
+    /// Required when the order caps purchases per buyer
+    #[account(
+        mut,
+        seeds = [b"purchase", sell_order.key().as_ref(), buyer.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Option<Account<'info, PurchaseRecord>>,
+
+    /// Required when the order records fills to a `FillFeed`
+    #[account(mut, seeds = [b"fill_feed", sell_order.key().as_ref()], bump = fill_feed.bump)]
+    pub fill_feed: Option<Account<'info, FillFeed>>,
+
+    /// Seller’s order index, cleaned up when passed and the fill sells out
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, cleaned up when passed and the fill sells out
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Receives the sale proceeds: the order’s `payout` address, else the seller
+    #[account(mut, address = sell_order.proceeds_recipient() @ SellError::InvalidPayout)]
+    pub proceeds: SystemAccount<'info>,
+
+    /// Receives the protocol fee
+    #[account(mut, address = config.treasury @ SellError::InvalidTreasury)]
+    pub treasury: SystemAccount<'info>,
+
+    /// Front‑end credited with the sale; takes `config.referral_bps` of the
+    /// protocol fee
+    #[account(mut)]
+    pub referrer: Option<SystemAccount<'info>>,
+
+    /// Holds the seller’s share of this fill; required exactly for orders
+    /// with a `release_delay`
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + PaymentReceipt::SIZE,
+        seeds = [
+            b"receipt",
+            sell_order.key().as_ref(),
+            sell_order.created_at.to_le_bytes().as_ref(),
+            sell_order.receipts_issued.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub payment_receipt: Option<Account<'info, PaymentReceipt>>,
+
+    /// Waives the protocol fee for this buyer
+    #[account(seeds = [b"fee_exemption", buyer.key().as_ref()], bump = fee_exemption.bump)]
+    pub fee_exemption: Option<Account<'info, FeeExemption>>,
+
+    /// Required when the order pays a royalty
+    #[account(mut, address = sell_order.royalty_creator @ SellError::InvalidRoyalty)]
+    pub royalty_creator: Option<SystemAccount<'info>>,
+
+    /// SOL/USD price feed; required for `UsdOracle` orders
+    /// CHECK: must be the order’s oracle; decoded by `oracle_lamport_price`
+    #[account(address = sell_order.oracle @ SellError::InvalidOracle)]
+    pub oracle: Option<UncheckedAccount<'info>>,
+
+    /// Program notified of the fill; required for `callback_required` orders
+    /// CHECK: must be the order’s `callback_program`
+    #[account(
+        executable,
+        address = sell_order.callback_program.unwrap_or_default() @ SellError::InvalidCallback,
+    )]
+    pub callback_program: Option<UncheckedAccount<'info>>,
+
+    /// Account of the callback program’s own, passed on writable
+    /// CHECK: validated by the callback program
+    #[account(mut)]
+    pub callback_state: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Fixed accounts of `buy_batch`; each leg comes in via `remaining_accounts`
+#[derive(Accounts)]
+pub struct BuyBatch<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Receives the protocol fee
+    #[account(mut, address = config.treasury @ SellError::InvalidTreasury)]
+    pub treasury: SystemAccount<'info>,
+
+    /// A seller’s order index; legs of that seller that sell out leave it
+    #[account(
+        mut,
+        seeds = [b"seller_index", seller_index.seller.as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// A mint’s order index; legs of that mint that sell out leave it
+    #[account(mut, seeds = [b"mint_index", mint_index.mint.as_ref()], bump = mint_index.bump)]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyWithToken<'info> {
+    pub buyer: Signer<'info>,
+
+    /// Seller receives the rent once the order is fully sold
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+
+    /// Order state
+    #[account(
+        mut,
+        has_one = seller,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Same token account as recorded in the order
+    #[account(mut)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = sell_order.token_mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// Buyer’s token account to receive tokens
+    #[account(mut)]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller’s account receiving the rest of an escrow order’s vault; only
+    /// needed for the fill that reaches its `max_fills`
+    #[account(mut, token::mint = sell_order.token_mint, token::authority = seller)]
+    pub seller_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = sell_order.payment_mint == Some(payment_mint.key())
+            @ SellError::WrongPaymentMethod,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    /// Buyer’s `payment_mint` account that pays for the tokens
+    #[account(mut, token::authority = buyer)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// `payment_mint` account of the order’s `payout` address (else the
+    /// seller) receiving the payment
+    #[account(
+        mut,
+        constraint = seller_payment_account.owner == sell_order.proceeds_recipient()
+            @ SellError::InvalidPayout,
+    )]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// PDA delegate that actually moves tokens; checked against the
+    /// order’s stored bump with a dedicated `InvalidAuthority` error
+    pub order_authority: SystemAccount<'info>,
+
+    /// Required when the order is whitelisted
+    #[account(seeds = [b"whitelist", sell_order.key().as_ref()], bump = whitelist.bump)]
+    pub whitelist: Option<Account<'info, Whitelist>>,
+
+    /// Required when the order caps purchases per buyer
+    #[account(
+        mut,
+        seeds = [b"purchase", sell_order.key().as_ref(), buyer.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Option<Account<'info, PurchaseRecord>>,
+
+    /// Required when the order records fills to a `FillFeed`
+    #[account(mut, seeds = [b"fill_feed", sell_order.key().as_ref()], bump = fill_feed.bump)]
+    pub fill_feed: Option<Account<'info, FillFeed>>,
+
+    /// Seller’s order index, cleaned up when passed and the fill sells out
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, cleaned up when passed and the fill sells out
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Treasury’s `payment_mint` account receiving the protocol fee
+    #[account(
+        mut,
+        constraint = treasury_payment_account.owner == config.treasury @ SellError::InvalidTreasury,
+    )]
+    pub treasury_payment_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Royalty creator’s `payment_mint` account; required when the order
+    /// pays a royalty
+    #[account(
+        mut,
+        constraint = royalty_payment_account.owner == sell_order.royalty_creator
+            @ SellError::InvalidRoyalty,
+    )]
+    pub royalty_payment_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Token program owning `payment_mint`, which may differ from the order
+    /// token’s
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive)]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + Bid::SIZE,
+        seeds = [b"bid", sell_order.key().as_ref(), bidder.key().as_ref()],
+        bump,
+    )]
+    pub bid: Account<'info, Bid>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = bidder,
+        close = bidder,
+        seeds = [b"bid", bid.sell_order.as_ref(), bidder.key().as_ref()],
+        bump = bid.bump,
+    )]
+    pub bid: Account<'info, Bid>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Closed to the bidder once the fill is paid out of it
+    #[account(
+        mut,
+        has_one = sell_order,
+        has_one = bidder,
+        close = bidder,
+        seeds = [b"bid", sell_order.key().as_ref(), bidder.key().as_ref()],
+        bump = bid.bump,
+    )]
+    pub bid: Account<'info, Bid>,
+
+    #[account(mut)]
+    pub bidder: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = sell_order.token_mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// Chosen by the seller, so it must belong to the bidder
+    #[account(
+        mut,
+        constraint = bidder_token_account.owner == bid.bidder @ SellError::InvalidBid,
+    )]
+    pub bidder_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller’s account receiving the rest of an escrow order’s vault; only
+    /// needed for the fill that reaches its `max_fills`
+    #[account(mut, token::mint = sell_order.token_mint, token::authority = seller)]
+    pub seller_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// PDA delegate that actually moves tokens
+    pub order_authority: SystemAccount<'info>,
+
+    /// Required when the order is whitelisted
+    #[account(seeds = [b"whitelist", sell_order.key().as_ref()], bump = whitelist.bump)]
+    pub whitelist: Option<Account<'info, Whitelist>>,
+
+    /// Required when the order caps purchases per buyer
+    #[account(
+        mut,
+        seeds = [b"purchase", sell_order.key().as_ref(), bidder.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Option<Account<'info, PurchaseRecord>>,
+
+    /// Required when the order records fills to a `FillFeed`
+    #[account(mut, seeds = [b"fill_feed", sell_order.key().as_ref()], bump = fill_feed.bump)]
+    pub fill_feed: Option<Account<'info, FillFeed>>,
+
+    /// Seller’s order index, cleaned up when passed and the fill sells out
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, cleaned up when passed and the fill sells out
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// The order’s `payout` address, else the seller
+    #[account(mut, address = sell_order.proceeds_recipient() @ SellError::InvalidPayout)]
+    pub proceeds: SystemAccount<'info>,
+
+    /// Receives the protocol fee
+    #[account(mut, address = config.treasury @ SellError::InvalidTreasury)]
+    pub treasury: SystemAccount<'info>,
+
+    /// Required when the order pays a royalty
+    #[account(mut, address = sell_order.royalty_creator @ SellError::InvalidRoyalty)]
+    pub royalty_creator: Option<SystemAccount<'info>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceAuctionBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    /// Holds the high bid until settlement
+    #[account(
+        mut,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Current high bidder, refunded when outbid; required once there is one
+    #[account(
+        mut,
+        address = sell_order.high_bidder.unwrap_or_default() @ SellError::InvalidPreviousBidder,
+    )]
+    pub previous_bidder: Option<SystemAccount<'info>>,
+
+    /// Required when the order is whitelisted
+    #[account(seeds = [b"whitelist", sell_order.key().as_ref()], bump = whitelist.bump)]
+    pub whitelist: Option<Account<'info, Whitelist>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    /// Receives the rent of the order and vault
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Escrow vault recorded in the order
+    #[account(
+        mut,
+        seeds = [
+            b"vault",
+            sell_order.creator.as_ref(),
+            sell_order.token_mint.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.vault_bump,
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = sell_order.token_mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// High bidder’s account receiving the lot
+    #[account(
+        mut,
+        constraint = Some(winner_token_account.owner) == sell_order.high_bidder
+            @ SellError::InvalidBid,
+    )]
+    pub winner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [sell_order.creator.as_ref(), token_account.key().as_ref()],
+        bump = sell_order.authority_bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// The order’s `payout` address, else the seller
+    #[account(mut, address = sell_order.proceeds_recipient() @ SellError::InvalidPayout)]
+    pub proceeds: SystemAccount<'info>,
+
+    /// Receives the protocol fee
+    #[account(mut, address = config.treasury @ SellError::InvalidTreasury)]
+    pub treasury: SystemAccount<'info>,
+
+    /// Required when the order pays a royalty
+    #[account(mut, address = sell_order.royalty_creator @ SellError::InvalidRoyalty)]
+    pub royalty_creator: Option<SystemAccount<'info>>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Reclaim<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        close = seller,
+        has_one = seller,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Escrow vault recorded in the order
+    #[account(
+        mut,
+        seeds = [
+            b"vault",
+            sell_order.creator.as_ref(),
+            sell_order.token_mint.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.vault_bump,
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = sell_order.token_mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// Seller’s account receiving the unsold tokens
+    #[account(mut, token::mint = sell_order.token_mint, token::authority = seller)]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [sell_order.creator.as_ref(), token_account.key().as_ref()],
+        bump = sell_order.authority_bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawProceeds<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// The order’s `payout` address, else the seller
+    #[account(mut, address = sell_order.proceeds_recipient() @ SellError::InvalidPayout)]
+    pub proceeds: SystemAccount<'info>,
+
+    /// Escrow vault; required for escrow orders, whose vault may still be
+    /// open when they sell out
+    /// CHECK: only its lamports are read
+    #[account(address = sell_order.token_account)]
+    pub vault: Option<UncheckedAccount<'info>>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+}
+
+#[derive(Accounts)]
+pub struct RescueTokens<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        has_one = seller,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(
+        seeds = [sell_order.creator.as_ref(), sell_order.token_account.as_ref()],
+        bump = sell_order.authority_bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    /// Token account owned by the authority PDA holding the stray tokens
+    #[account(
+        mut,
+        token::authority = order_authority,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub stray_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = stray_token_account.mint @ SellError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Any account of the same mint chosen by the seller
+    #[account(mut, token::mint = mint)]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitWhitelist<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + Whitelist::SIZE,
+        seeds = [b"whitelist", sell_order.key().as_ref()],
+        bump,
+    )]
+    pub whitelist: Account<'info, Whitelist>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitFillFeed<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + FillFeed::SIZE,
+        seeds = [b"fill_feed", sell_order.key().as_ref()],
+        bump,
+    )]
+    pub fill_feed: Account<'info, FillFeed>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateWhitelist<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(has_one = seller)]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(
+        mut,
+        has_one = sell_order,
+        seeds = [b"whitelist", sell_order.key().as_ref()],
+        bump = whitelist.bump,
+    )]
+    pub whitelist: Account<'info, Whitelist>,
+}
+
+#[derive(Accounts)]
+pub struct InitPurchaseRecord<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + PurchaseRecord::SIZE,
+        seeds = [b"purchase", sell_order.key().as_ref(), buyer.key().as_ref()],
+        bump,
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct HealthCheck<'info> {
+    #[account(mut, has_one = token_account)]
+    pub sell_order: Account<'info, SellOrder>,
+
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = sell_order.authority_address()? @ SellError::InvalidAuthority)]
+    pub order_authority: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitSellerIndex<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + SellerIndex::SIZE,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump,
+    )]
+    pub seller_index: Account<'info, SellerIndex>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PruneSellerIndex<'info> {
+    #[account(
+        mut,
+        seeds = [b"seller_index", seller_index.seller.as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Account<'info, SellerIndex>,
+}
+
+#[derive(Accounts)]
+pub struct InitMintIndex<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + MintIndex::SIZE,
+        seeds = [b"mint_index", token_mint.key().as_ref()],
+        bump,
+    )]
+    pub mint_index: Account<'info, MintIndex>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PruneMintIndex<'info> {
+    #[account(
+        mut,
+        seeds = [b"mint_index", mint_index.mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Account<'info, MintIndex>,
+}
+
+/// Read‑only access to an order for simulation‑only views
+#[derive(Accounts)]
+pub struct ViewOrder<'info> {
+    pub sell_order: Account<'info, SellOrder>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct MigrateOrder<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Receives the rent of the v1 account
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+
+    /// Order in layout v1, at its address without an `order_id`
+    /// CHECK: `Account` cannot decode the old layout; the discriminator and
+    /// size are validated by `migrate_order`
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"sell_order", seller.key().as_ref(), token_account.key().as_ref()],
+        bump,
+    )]
+    pub legacy_order: UncheckedAccount<'info>,
+
+    /// Seller’s token account the order sells from
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Mint of the order, for the decimals v1 did not record
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// The order in the current layout
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SellOrder::SIZE,
+        seeds = [
+            b"sell_order",
+            seller.key().as_ref(),
+            token_account.key().as_ref(),
+            order_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Config::SIZE,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Seller changes the quantity of a live order (re‑approving the delegate)
+#[derive(Accounts)]
+pub struct ResizeOrder<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(mut)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [sell_order.creator.as_ref(), token_account.key().as_ref()],
+        bump = sell_order.authority_bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Seller tops up the vault of a live escrow order
+#[derive(Accounts)]
+pub struct DepositMore<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        has_one = token_account,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Escrow vault recorded in the order
+    #[account(
+        mut,
+        seeds = [
+            b"vault",
+            sell_order.creator.as_ref(),
+            sell_order.token_mint.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.vault_bump,
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = sell_order.token_mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// Seller’s account the tokens are taken from
+    #[account(mut, token::mint = sell_order.token_mint, token::authority = seller)]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AddSource<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Another of the seller's accounts for the order's mint
+    #[account(
+        mut,
+        token::authority = seller,
+        constraint = source_token_account.mint == sell_order.token_mint @ SellError::MintMismatch,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub source_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Delegate PDA of the source account
+    #[account(
+        seeds = [sell_order.creator.as_ref(), source_token_account.key().as_ref()],
+        bump,
+    )]
+    pub source_authority: SystemAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitMintAllowlist<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(has_one = admin, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + MintAllowlist::SIZE,
+        seeds = [b"mint_allowlist"],
+        bump,
+    )]
+    pub mint_allowlist: Account<'info, MintAllowlist>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMintAllowlist<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(has_one = admin, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut, seeds = [b"mint_allowlist"], bump = mint_allowlist.bump)]
+    pub mint_allowlist: Account<'info, MintAllowlist>,
+}
+
+#[derive(Accounts)]
+pub struct ReleasePayment<'info> {
+    /// The buyer, or anyone once the receipt is due
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        close = buyer,
+        has_one = buyer,
+        has_one = payee,
+        seeds = [
+            b"receipt",
+            payment_receipt.sell_order.as_ref(),
+            payment_receipt.order_created_at.to_le_bytes().as_ref(),
+            payment_receipt.fill_index.to_le_bytes().as_ref(),
+        ],
+        bump = payment_receipt.bump,
+    )]
+    pub payment_receipt: Account<'info, PaymentReceipt>,
+
+    /// Paid the receipt’s rent and gets it back
+    #[account(mut)]
+    pub buyer: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub payee: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(buyer: Pubkey)]
+pub struct GrantExemption<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(has_one = admin, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + FeeExemption::SIZE,
+        seeds = [b"fee_exemption", buyer.as_ref()],
+        bump,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeExemption<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(has_one = admin, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"fee_exemption", fee_exemption.buyer.as_ref()],
+        bump = fee_exemption.bump,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+}
+
+/// Admin‑only changes to the global config
+#[derive(Accounts)]
+pub struct AdminConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct TransferOwnership<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+/// Seller‑only changes to the terms of a live order
+#[derive(Accounts)]
+pub struct UpdateOrder<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrice<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct CloseExpired<'info> {
+    pub caller: Signer<'info>,
+
+    /// Receives the order’s rent
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        close = seller,
+        has_one = seller,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            sell_order.token_account.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAll<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// A mint’s order index; cancelled orders of that mint leave it
+    #[account(mut, seeds = [b"mint_index", mint_index.mint.as_ref()], bump = mint_index.bump)]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: OrderParams)]
+pub struct CancelAndRecreate<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// Order being replaced
+    #[account(
+        mut,
+        close = seller,
+        has_one = seller,
+        constraint = old_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        seeds = [
+            b"sell_order",
+            old_order.creator.as_ref(),
+            old_token_account.key().as_ref(),
+            old_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = old_order.order_bump,
+    )]
+    pub old_order: Account<'info, SellOrder>,
+
+    #[account(mut)]
+    pub old_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [old_order.creator.as_ref(), old_token_account.key().as_ref()],
+        bump = old_order.authority_bump,
+    )]
+    pub old_authority: SystemAccount<'info>,
+
+    /// Token account backing the new order; may be `old_token_account`
+    #[account(mut, owner = token_program.key() @ SellError::WrongTokenProgram)]
+    pub new_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = new_token_account.mint @ SellError::MintMismatch,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [seller.key().as_ref(), new_token_account.key().as_ref()],
+        bump,
+    )]
+    pub new_authority: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Admin mint allowlist; when it has been created only listed mints
+    /// can be sold
+    /// CHECK: may not exist yet, see `check_mint_allowed`
+    #[account(seeds = [b"mint_allowlist"], bump)]
+    pub mint_allowlist: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellOrder::SIZE,
+        seeds = [
+            b"sell_order",
+            seller.key().as_ref(),
+            new_token_account.key().as_ref(),
+            params.order_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub new_order: Account<'info, SellOrder>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", token_mint.key().as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Relist<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = seller,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(mut)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = sell_order.token_mint,
+        owner = token_program.key() @ SellError::WrongTokenProgram,
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [sell_order.creator.as_ref(), token_account.key().as_ref()],
+        bump = sell_order.authority_bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Admin mint allowlist; when it has been created only listed mints
+    /// can be sold
+    /// CHECK: may not exist yet, see `check_mint_allowed`
+    #[account(seeds = [b"mint_allowlist"], bump)]
+    pub mint_allowlist: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Cancel<'info> {
+    /// The seller, or the order’s `cancel_authority`
+    pub authority: Signer<'info>,
+
+    /// Receives the order’s rent
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        close = seller,
+        has_one = seller,
+        constraint = authority.key() == sell_order.seller
+            || sell_order.cancel_authority == Some(authority.key())
+            @ SellError::NotCancelAuthority,
+        constraint = sell_order.status == OrderStatus::Active @ SellError::OrderNotActive,
+        has_one = token_account,
+        seeds = [
+            b"sell_order",
+            sell_order.creator.as_ref(),
+            token_account.key().as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref(),
+        ],
+        bump = sell_order.order_bump,
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+
+    #[account(mut)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [sell_order.creator.as_ref(), token_account.key().as_ref()],
+        bump = sell_order.authority_bump,
+    )]
+    pub order_authority: SystemAccount<'info>,
+
+    /// Seller’s order index, kept up to date when passed
+    #[account(
+        mut,
+        has_one = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump,
+    )]
+    pub seller_index: Option<Account<'info, SellerIndex>>,
+
+    /// Mint’s order index, kept up to date when passed
+    #[account(
+        mut,
+        seeds = [b"mint_index", sell_order.token_mint.as_ref()],
+        bump = mint_index.bump,
+    )]
+    pub mint_index: Option<Account<'info, MintIndex>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// ============================================================================
+// Instruction arguments
+// ============================================================================
+/// Terms supplied by the seller when opening an order
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderParams {
+    /// Seller‑chosen id, so one token account can back several orders
+    pub order_id: u64,
+    pub amount: u64,
+    pub price_per_token: u64, // lamports, or `payment_mint` base units if set
+    pub deadline: i64,        // unix timestamp (UTC)
+    /// Buys are rejected before this time; 0 goes live immediately
+    pub start_time: i64,
+    pub payment_mint: Option<Pubkey>,
+    pub min_purchase: u64, // 0 disables the minimum
+    /// Restrict the order to a single buyer (OTC deals)
+    pub allowed_buyer: Option<Pubkey>,
+    /// Send proceeds here instead of to the seller
+    pub payout: Option<Pubkey>,
+    /// Hold lamport proceeds on the order until `withdraw_proceeds`
+    pub escrow_proceeds: bool,
+    /// Most any one buyer may take in total; 0 disables the cap
+    pub max_per_buyer: u64,
+    pub pricing_mode: PricingMode,
+    /// Final price of a Dutch auction (`price_per_token` is the start price)
+    pub end_price: u64,
+    /// Fixed‑price volume discounts, by ascending `min_quantity`
+    pub tiers: Vec<PriceTier>,
+    /// Require the mint to be an NFT (supply 1, 0 decimals) sold whole
+    pub is_nft: bool,
+    /// Share of every sale paid to `royalty_creator` (NFT orders only)
+    pub royalty_bps: u16,
+    pub royalty_creator: Pubkey,
+    /// `false` commits the seller to not cancel or shrink the order before
+    /// the deadline
+    pub cancellable_before_deadline: bool,
+    pub fill_mode: FillMode,
+    /// Floor of a Dutch auction; 0 disables it
+    pub reserve_price: u64,
+    /// Seconds after the deadline during which buys still succeed
+    pub grace_period: i64,
+    /// Premium on the price during the grace period
+    pub grace_premium_bps: u16,
+    /// Lamport price per token also accepted by a `payment_mint` order;
+    /// 0 accepts `payment_mint` only
+    pub sol_price_per_token: u64,
+    /// Prices must be multiples of this; 0 disables the grid
+    pub tick_size: u64,
+    /// `UsdOracle` price in `USD_DECIMALS` fixed point
+    pub usd_price_per_token: u64,
+    /// SOL/USD price feed for `UsdOracle` orders
+    pub oracle: Pubkey,
+    /// Raise a fixed price by this much every `price_step_amount` tokens
+    /// sold; 0 disables scarcity pricing
+    pub price_step_bps: u16,
+    pub price_step_amount: u64,
+    /// Close the order once fewer than this many tokens remain (delegated
+    /// orders only); 0 disables it
+    pub dust_threshold: u64,
+    /// Seconds before the deadline in which `closeout_bps` comes off the
+    /// price; 0 disables the closeout discount
+    pub closeout_window: i64,
+    pub closeout_bps: u16,
+    /// Seconds before the deadline from which the next `buy` or
+    /// `health_check` emits `OrderExpiringSoon`; 0 disables the reminder
+    pub reminder_window: i64,
+    /// Operator that may `cancel` the order besides the seller
+    pub cancel_authority: Option<Pubkey>,
+    /// Free‑form tag for marketplaces (collection name, SKU, …); opaque to
+    /// the program, conventionally zero‑padded UTF‑8
+    pub label: [u8; 32],
+    /// Program whose `on_fill` entrypoint lamport buys CPI after each fill
+    pub callback_program: Option<Pubkey>,
+    /// Every fill must reach `callback_program`
+    pub callback_required: bool,
+    /// List this share (bps) of the token account balance instead of a
+    /// fixed `amount`, which must then be 0 (creation only)
+    pub percentage_of_balance: Option<u16>,
+    /// Hold the seller’s share of each lamport fill in a `PaymentReceipt`
+    /// until the buyer releases it or this many seconds pass; 0 pays out
+    /// at once
+    pub release_delay: i64,
+    /// Stop selling after this many fills, whatever is left.  Escrow orders
+    /// then return the rest of the vault to the seller and close; delegated
+    /// orders stay open until the seller's `cancel` releases the rest of
+    /// the allowance.  0 disables the cap
+    pub max_fills: u32,
+}
+
+/// How the per‑token price of an order evolves over time
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PricingMode {
+    /// `price_per_token` until the deadline (or `update_price`)
+    Fixed,
+    /// Linear decline from `start_price` to `end_price` over the order life
+    DutchAuction,
+    /// `usd_price_per_token`, charged in lamports at the `oracle` SOL/USD
+    /// rate of each buy
+    UsdOracle,
+    /// Open auction of the whole lot to the highest bidder, opening at
+    /// `price_per_token` per token (escrow orders only)
+    EnglishAuction,
+}
+
+/// Returned by `get_status`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderStatusView {
+    pub amount_remaining: u64,
+    /// 0 once the deadline has passed
+    pub seconds_until_deadline: u64,
+    /// Past the deadline and any grace period, so buys fail
+    pub expired: bool,
+    pub status: OrderStatus,
+}
+
+/// Whether an order may be filled piecemeal
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    Partial,
+    /// Each fill must take the whole `amount_remaining` (block trades)
+    AllOrNothing,
+}
+
+/// Lifecycle of an order; only `Active` orders can be filled or changed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    Active,
+    /// Not stored: cancelling closes the order account in the same
+    /// instruction
+    Cancelled,
+    /// Sold out; an order holding escrowed proceeds stays in this state
+    /// until `withdraw_proceeds`
+    Filled,
+}
+
+/// Extra seller token account an order draws from once its own account is
+/// exhausted, delegating to its own authority PDA
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenSource {
+    pub token_account: Pubkey,
+    pub authority_bump: u8,
+}
+
+impl TokenSource {
+    pub fn authority_seeds<'a>(&'a self, creator: &'a Pubkey) -> [&'a [u8]; 3] {
+        [
+            creator.as_ref(),
+            self.token_account.as_ref(),
+            std::slice::from_ref(&self.authority_bump),
+        ]
+    }
+
+    /// Address of the source's delegate PDA, from the stored bump.
+    pub fn authority_address(&self, creator: &Pubkey) -> Result<Pubkey> {
+        Pubkey::create_program_address(&self.authority_seeds(creator), &crate::ID)
+            .map_err(|_| ProgramError::InvalidSeeds.into())
+    }
+}
+
+/// Volume discount: fills of at least `min_quantity` pay `price_per_token`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceTier {
+    pub min_quantity: u64,
+    pub price_per_token: u64,
+}
+
+// ============================================================================
+// State
+// ============================================================================
+#[account]
+pub struct SellOrder {
+    /// Account layout version, see `SellOrder::VERSION`
+    pub version: u8,
+    pub order_id: u64,
+    pub seller: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub amount_remaining: u64,
+    pub price_per_token: u64,
+    pub deadline: i64,
+    /// SPL mint the order is priced in; `None` means lamports
+    pub payment_mint: Option<Pubkey>,
+    /// Smallest quantity a single `buy` may take (except the final remainder)
+    pub min_purchase: u64,
+    /// Tokens sit in a program‑owned vault (`token_account`) instead of
+    /// being delegated from the seller’s account
+    pub escrowed: bool,
+    /// Only this buyer may fill the order
+    pub allowed_buyer: Option<Pubkey>,
+    /// Proceeds go here instead of `seller` (who keeps order authority)
+    pub payout: Option<Pubkey>,
+    /// Only buyers listed in the order’s `Whitelist` PDA may fill the order
+    pub whitelisted: bool,
+    pub pricing_mode: PricingMode,
+    /// Dutch auction price at `created_at`
+    pub start_price: u64,
+    /// Dutch auction price at `deadline`
+    pub end_price: u64,
+    /// Unix timestamp the order was opened at
+    pub created_at: i64,
+    /// Canonical bump of the `order_authority` delegate PDA
+    pub authority_bump: u8,
+    /// Canonical bump of this `sell_order` PDA
+    pub order_bump: u8,
+    /// Tokens sold so far across all fills (added in layout v2)
+    pub total_filled: u64,
+    /// Number of fills so far
+    pub num_fills: u32,
+    /// Lamport proceeds are held on this account until `withdraw_proceeds`
+    pub escrow_proceeds: bool,
+    /// Escrowed proceeds not yet withdrawn
+    pub proceeds_collected: u64,
+    /// Decimals of `token_mint`; amounts and prices are in base units
+    pub token_decimals: u8,
+    /// Seller that opened the order; the order and authority PDAs derive
+    /// from it even after `transfer_ownership`
+    pub creator: Pubkey,
+    /// Per‑buyer cap tracked through `PurchaseRecord`s; 0 disables it
+    pub max_per_buyer: u64,
+    /// Volume discounts on top of `price_per_token` (at most `MAX_TIERS`)
+    pub tiers: Vec<PriceTier>,
+    /// Buys open at this time
+    pub start_time: i64,
+    pub status: OrderStatus,
+    /// Bump of the escrow vault PDA; 0 for delegated orders
+    pub vault_bump: u8,
+    /// Mint was validated as an NFT at creation
+    pub is_nft: bool,
+    /// Royalty cut of each sale, paid to `royalty_creator`
+    pub royalty_bps: u16,
+    pub royalty_creator: Pubkey,
+    /// Sum of fill price × seconds since the previous fill, for TWAPs
+    pub cumulative_price_time: u128,
+    /// Time of the last fill (`created_at` before the first)
+    pub last_fill_time: i64,
+    /// Seller may cancel or shrink the order before the deadline
+    pub cancellable_before_deadline: bool,
+    pub fill_mode: FillMode,
+    /// Floor of a Dutch auction; 0 disables it
+    pub reserve_price: u64,
+    /// Seconds after the deadline during which buys still succeed
+    pub grace_period: i64,
+    /// Premium on the price during the grace period
+    pub grace_premium_bps: u16,
+    /// Lamport price per token also accepted by a `payment_mint` order;
+    /// 0 accepts `payment_mint` only
+    pub sol_price_per_token: u64,
+    /// Prices must be multiples of this; 0 disables the grid
+    pub tick_size: u64,
+    /// `UsdOracle` price in `USD_DECIMALS` fixed point
+    pub usd_price_per_token: u64,
+    /// SOL/USD price feed for `UsdOracle` orders
+    pub oracle: Pubkey,
+    /// Scarcity pricing: `price_per_token` rises by `price_step_bps` each
+    /// time another `price_step_amount` tokens are sold
+    pub price_step_bps: u16,
+    pub price_step_amount: u64,
+    /// A fill leaving fewer tokens than this closes the order
+    pub dust_threshold: u64,
+    /// Further token accounts drawn from after `token_account`, in order
+    pub sources: Vec<TokenSource>,
+    /// Discount taken off the price in the last `closeout_window` seconds
+    /// before the deadline
+    pub closeout_window: i64,
+    pub closeout_bps: u16,
+    /// Every fill is written to the order’s `FillFeed` PDA
+    pub records_fills: bool,
+    /// Seller has paused fills with `pause_order`
+    pub paused: bool,
+    /// `OrderExpiringSoon` is emitted once per deadline, this many seconds
+    /// ahead of it
+    pub reminder_window: i64,
+    pub reminder_sent: bool,
+    /// May `cancel` the order on the seller’s behalf
+    pub cancel_authority: Option<Pubkey>,
+    /// Marketplace tag set at creation
+    pub label: [u8; 32],
+    /// Best `EnglishAuction` bid so far, held on this account
+    pub high_bid: u64,
+    pub high_bidder: Option<Pubkey>,
+    /// Notified of lamport buys, see `buy`
+    pub callback_program: Option<Pubkey>,
+    pub callback_required: bool,
+    /// Share of the balance the order was sized from, if any
+    pub percentage_of_balance: Option<u16>,
+    /// Time of the last `update_price`; 0 if never repriced
+    pub last_price_update: i64,
+    /// Lamport fills pay the seller through a `PaymentReceipt` that
+    /// releases this many seconds after the fill
+    pub release_delay: i64,
+    /// The fill that brings `num_fills` to this ends the sale
+    pub max_fills: u32,
+    /// `PaymentReceipt`s ever issued; unlike `num_fills` never reset, so
+    /// receipts of a relisted order keep distinct addresses.  A reopened
+    /// order starts again at 0 but under a new `created_at`
+    pub receipts_issued: u32,
+}
+
+impl SellOrder {
+    /// Current account layout; older accounts go through `migrate_order`.
+    /// Once deployed, any change to the fields below bumps this and keeps
+    /// the replaced layout as a `SellOrderV{n}` that `migrate_order` reads.
+    pub const VERSION: u8 = 2;
+
+    // discriminator (8 bytes) is added separately at `init`
+    pub const SIZE: usize = 1 // version
+        + 8 // order_id
+        + 32 * 3 // seller, token_mint, token_account
+        + 8 * 4 // amount_remaining, price_per_token, deadline, min_purchase
+        + (1 + 32) // payment_mint
+        + 1 // escrowed
+        + (1 + 32) // allowed_buyer
+        + (1 + 32) // payout
+        + 1 // whitelisted
+        + 1 // pricing_mode
+        + 8 * 3 // start_price, end_price, created_at
+        + 2 // authority_bump, order_bump
+        + 8 // total_filled
+        + 4 // num_fills
+        + 1 // escrow_proceeds
+        + 8 // proceeds_collected
+        + 1 // token_decimals
+        + 32 // creator
+        + 8 // max_per_buyer
+        + (4 + 16 * MAX_TIERS) // tiers
+        + 8 // start_time
+        + 1 // status
+        + 1 // vault_bump
+        + 1 // is_nft
+        + 2 // royalty_bps
+        + 32 // royalty_creator
+        + 16 // cumulative_price_time
+        + 8 // last_fill_time
+        + 1 // cancellable_before_deadline
+        + 1 // fill_mode
+        + 8 // reserve_price
+        + 8 // grace_period
+        + 2 // grace_premium_bps
+        + 8 // sol_price_per_token
+        + 8 // tick_size
+        + 8 // usd_price_per_token
+        + 32 // oracle
+        + 2 // price_step_bps
+        + 8 // price_step_amount
+        + 8 // dust_threshold
+        + (4 + 33 * MAX_SOURCE_ACCOUNTS) // sources
+        + 8 // closeout_window
+        + 2 // closeout_bps
+        + 1 // records_fills
+        + 1 // paused
+        + 8 // reminder_window
+        + 1 // reminder_sent
+        + (1 + 32) // cancel_authority
+        + 32 // label
+        + 8 // high_bid
+        + (1 + 32) // high_bidder
+        + (1 + 32) // callback_program
+        + 1 // callback_required
+        + (1 + 2) // percentage_of_balance
+        + 8 // last_price_update
+        + 8 // release_delay
+        + 4 // max_fills
+        + 4; // receipts_issued
+
+    /// Decode an order from raw account data, as fetched by a client,
+    /// checking the discriminator and that the account is in the current
+    /// layout.
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        require!(data.len() == 8 + Self::SIZE, SellError::InvalidOrderLayout);
+        Self::try_deserialize(&mut &data[..])
+    }
+
+    /// Validate `params` and fill in a freshly initialized order.
+    pub fn open(
+        &mut self,
+        params: &OrderParams,
+        seller: Pubkey,
+        token_mint: Pubkey,
+        token_account: Pubkey,
+        escrowed: bool,
+        now: i64,
+    ) -> Result<()> {
+        // --- sanity checks ---------------------------------------------------
+        // a previously opened order at this PDA is still live
+        require!(
+            self.seller == Pubkey::default(),
+            SellError::OrderAlreadyExists
+        );
+        require!(params.amount > 0, SellError::InvalidAmount);
+        // oracle orders are priced in `usd_price_per_token` instead
+        require!(
+            params.price_per_token > 0 || params.pricing_mode == PricingMode::UsdOracle,
+            SellError::InvalidPrice
+        );
+        require!(
+            params.min_purchase <= params.amount,
+            SellError::InvalidAmount
+        );
+        require!(params.deadline > now, SellError::DeadlineInPast);
+        require!(
+            params.deadline >= now.saturating_add(MIN_DURATION),
+            SellError::DeadlineTooSoon
+        );
+        require!(
+            params.deadline <= now.saturating_add(MAX_DURATION),
+            SellError::DeadlineTooFar
+        );
+        require!(
+            params.start_time < params.deadline,
+            SellError::InvalidStartTime
+        );
+        require!(
+            (0..=MAX_DURATION).contains(&params.grace_period)
+                && params.grace_premium_bps <= MAX_FEE_BPS,
+            SellError::InvalidGracePeriod
+        );
+        require!(
+            (0..=MAX_DURATION).contains(&params.closeout_window)
+                && params.closeout_bps < MAX_FEE_BPS,
+            SellError::InvalidCloseout
+        );
+        require!(
+            (0..=MAX_DURATION).contains(&params.reminder_window),
+            SellError::InvalidReminderWindow
+        );
+        require!(
+            !params.escrow_proceeds || params.payment_mint.is_none(),
+            SellError::WrongPaymentMethod
+        );
+        // held payments are lamports routed through `buy` alone
+        if params.release_delay != 0 {
+            require!(
+                params.release_delay > 0
+                    && params.release_delay <= MAX_DURATION
+                    && params.payment_mint.is_none()
+                    && !params.escrow_proceeds
+                    && params.pricing_mode != PricingMode::EnglishAuction,
+                SellError::InvalidReleaseDelay
+            );
+        }
+        // a vault can only be closed empty, and `buy` has nowhere to return
+        // the dust to
+        require!(
+            params.dust_threshold == 0 || !escrowed,
+            SellError::InvalidDustThreshold
+        );
+        require!(
+            params.sol_price_per_token == 0 || params.payment_mint.is_some(),
+            SellError::InvalidPrice
+        );
+        let tick = params.tick_size;
+        require!(
+            on_tick(params.price_per_token, tick)
+                && on_tick(params.end_price, tick)
+                && on_tick(params.reserve_price, tick)
+                && params
+                    .tiers
+                    .iter()
+                    .all(|tier| on_tick(tier.price_per_token, tick)),
+            SellError::PriceNotOnTick
+        );
+        if params.pricing_mode == PricingMode::DutchAuction {
+            require!(
+                params.end_price > 0 && params.end_price <= params.price_per_token,
+                SellError::InvalidPrice
+            );
+            require!(
+                params.reserve_price <= params.price_per_token,
+                SellError::InvalidPrice
+            );
+        } else {
+            require!(params.reserve_price == 0, SellError::InvalidPricingMode);
+        }
+        if params.pricing_mode == PricingMode::EnglishAuction {
+            require!(
+                escrowed
+                    && params.payment_mint.is_none()
+                    && !params.escrow_proceeds
+                    && params.grace_period == 0,
+                SellError::InvalidAuction
+            );
+        }
+        if let Some(program) = params.callback_program {
+            require_keys_neq!(program, crate::ID, SellError::InvalidCallback);
+        }
+        if params.callback_required {
+            // only lamport buys fire the callback
+            require!(
+                params.callback_program.is_some()
+                    && params.payment_mint.is_none()
+                    && params.pricing_mode != PricingMode::EnglishAuction,
+                SellError::InvalidCallback
+            );
+        }
+        if params.pricing_mode == PricingMode::UsdOracle {
+            require!(params.usd_price_per_token > 0, SellError::InvalidPrice);
+            require!(params.payment_mint.is_none(), SellError::WrongPaymentMethod);
+            require!(params.oracle != Pubkey::default(), SellError::InvalidOracle);
+        }
+        if params.price_step_bps > 0 {
+            require!(
+                params.pricing_mode == PricingMode::Fixed
+                    && params.price_step_bps <= MAX_FEE_BPS
+                    && params.price_step_amount > 0
+                    && params.amount / params.price_step_amount <= MAX_PRICE_STEPS,
+                SellError::InvalidPriceStep
+            );
+        }
+        if params.royalty_bps > 0 {
+            require!(
+                params.is_nft && params.royalty_bps <= MAX_FEE_BPS,
+                SellError::InvalidRoyalty
+            );
+        }
+        if !params.tiers.is_empty() {
+            require!(
+                params.pricing_mode == PricingMode::Fixed,
+                SellError::InvalidPricingMode
+            );
+            require!(params.tiers.len() <= MAX_TIERS, SellError::InvalidTiers);
+            let mut min_quantity = 0;
+            for tier in &params.tiers {
+                require!(tier.min_quantity > min_quantity, SellError::InvalidTiers);
+                require!(
+                    tier.price_per_token > 0 && tier.price_per_token <= params.price_per_token,
+                    SellError::InvalidTiers
+                );
+                min_quantity = tier.min_quantity;
+            }
+        }
+
+        self.version = Self::VERSION;
+        self.order_id = params.order_id;
+        self.seller = seller;
+        self.token_mint = token_mint;
+        self.token_account = token_account;
+        self.amount_remaining = params.amount;
+        self.price_per_token = params.price_per_token;
+        self.deadline = params.deadline;
+        self.payment_mint = params.payment_mint;
+        self.min_purchase = params.min_purchase;
+        self.allowed_buyer = params.allowed_buyer;
+        self.payout = params.payout;
+        self.whitelisted = false;
+        self.pricing_mode = params.pricing_mode;
+        self.start_price = params.price_per_token;
+        self.end_price = params.end_price;
+        self.created_at = now;
+        self.escrowed = escrowed;
+        self.total_filled = 0;
+        self.num_fills = 0;
+        self.escrow_proceeds = params.escrow_proceeds;
+        self.proceeds_collected = 0;
+        self.creator = seller;
+        self.max_per_buyer = params.max_per_buyer;
+        self.tiers = params.tiers.clone();
+        self.start_time = params.start_time;
+        self.status = OrderStatus::Active;
+        self.vault_bump = 0;
+        self.is_nft = params.is_nft;
+        self.royalty_bps = params.royalty_bps;
+        self.royalty_creator = params.royalty_creator;
+        self.cumulative_price_time = 0;
+        self.last_fill_time = now;
+        self.cancellable_before_deadline = params.cancellable_before_deadline;
+        self.fill_mode = params.fill_mode;
+        self.reserve_price = params.reserve_price;
+        self.grace_period = params.grace_period;
+        self.grace_premium_bps = params.grace_premium_bps;
+        self.sol_price_per_token = params.sol_price_per_token;
+        self.tick_size = params.tick_size;
+        self.usd_price_per_token = params.usd_price_per_token;
+        self.oracle = params.oracle;
+        self.price_step_bps = params.price_step_bps;
+        self.price_step_amount = params.price_step_amount;
+        self.dust_threshold = params.dust_threshold;
+        self.sources = Vec::new();
+        self.closeout_window = params.closeout_window;
+        self.closeout_bps = params.closeout_bps;
+        self.records_fills = false;
+        self.paused = false;
+        self.reminder_window = params.reminder_window;
+        self.reminder_sent = false;
+        self.cancel_authority = params.cancel_authority;
+        self.label = params.label;
+        self.high_bid = 0;
+        self.high_bidder = None;
+        self.callback_program = params.callback_program;
+        self.callback_required = params.callback_required;
+        self.percentage_of_balance = params.percentage_of_balance;
+        // `last_price_update` is kept so relisting cannot skip the cooldown
+        self.release_delay = params.release_delay;
+        self.max_fills = params.max_fills;
+        Ok(())
+    }
+
+    /// The order has taken its `max_fills` fills and sells no more.  Only a
+    /// delegated order is still open then, as its allowance can only be
+    /// released by the seller's `cancel`.
+    pub fn fills_capped(&self) -> bool {
+        self.max_fills > 0 && self.num_fills >= self.max_fills
+    }
+
+    /// Checks shared by every purchase path.
+    pub fn validate_fill(&self, now: i64, amount: u64) -> Result<()> {
+        require!(
+            self.status == OrderStatus::Active,
+            SellError::OrderNotActive
+        );
+        require!(!self.fills_capped(), SellError::FillCapReached);
+        require!(!self.paused, SellError::OrderPaused);
+        require!(now >= self.start_time, SellError::OrderNotStarted);
+        require!(now <= self.closes_at(), SellError::OrderExpired);
+        require!(
+            amount > 0 && amount <= self.amount_remaining,
+            SellError::InvalidAmount
+        );
+        require!(
+            self.fill_mode == FillMode::Partial || amount == self.amount_remaining,
+            SellError::MustFillEntireOrder
+        );
+        // a remainder smaller than the minimum may always be swept
+        require!(
+            amount >= self.min_purchase || amount == self.amount_remaining,
+            SellError::BelowMinimumPurchase
+        );
+        Ok(())
+    }
+
+    /// Per‑token price a fill of `amount` pays at `now`: the best tier the
+    /// amount qualifies for, never above the current base price.
+    pub fn price_for(&self, amount: u64, now: i64) -> Result<u64> {
+        let base = self.price_at(now)?;
+        Ok(self
+            .tiers
+            .iter()
+            .rev()
+            .find(|tier| amount >= tier.min_quantity)
+            .map_or(base, |tier| tier.price_per_token.min(base)))
+    }
+
+    /// Fold `price_per_token` over the time since the last fill into
+    /// `cumulative_price_time`.  The TWAP between two observations is the
+    /// accumulator delta divided by the `last_fill_time` delta.
+    pub fn accrue_price_time(&mut self, price_per_token: u64, now: i64) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_fill_time).max(0) as u128;
+        self.cumulative_price_time = self
+            .cumulative_price_time
+            .checked_add(price_per_token as u128 * elapsed)
+            .ok_or(SellError::MathOverflow)?;
+        self.last_fill_time = now;
+        Ok(())
+    }
+
+    /// Apply the scarcity steps crossed since `total_filled` was
+    /// `filled_before`, compounding `price_step_bps` once per step (rounded
+    /// up, then onto the tick grid).  Returns the previous price if it
+    /// changed.  The fill that crosses a step still pays the old price.
+    pub fn step_price(&mut self, filled_before: u64) -> Result<Option<u64>> {
+        if self.price_step_bps == 0 {
+            return Ok(None);
+        }
+        // `increase_order` can push the lifetime step count past the bound
+        // checked at creation
+        let reached = (self.total_filled / self.price_step_amount).min(MAX_PRICE_STEPS);
+        let steps = reached.saturating_sub(filled_before / self.price_step_amount);
+        if steps == 0 {
+            return Ok(None);
+        }
+
+        let mut price = self.price_per_token;
+        for _ in 0..steps {
+            price = price
+                .checked_add(fee_for(price, self.price_step_bps)?)
+                .ok_or(SellError::MathOverflow)?;
+        }
+        if self.tick_size > 0 {
+            price = price
+                .div_ceil(self.tick_size)
+                .checked_mul(self.tick_size)
+                .ok_or(SellError::MathOverflow)?;
+        }
+        let old_price = self.price_per_token;
+        self.price_per_token = price;
+        Ok(Some(old_price))
+    }
+
+    /// Address of the order PDA itself, from the stored bump.
+    pub fn order_address(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(
+            &[
+                b"sell_order",
+                self.creator.as_ref(),
+                self.token_account.as_ref(),
+                &self.order_id.to_le_bytes(),
+                &[self.order_bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| ProgramError::InvalidSeeds.into())
+    }
+
+    /// Address of the order authority PDA, from the stored bump.
+    pub fn authority_address(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(&self.authority_seeds(), &crate::ID)
+            .map_err(|_| ProgramError::InvalidSeeds.into())
+    }
+
+    /// Signer seeds of the order authority PDA.  Every CPI the authority
+    /// signs goes through this so the seeds cannot drift from the
+    /// `order_authority` constraints.
+    pub fn authority_seeds(&self) -> [&[u8]; 3] {
+        [
+            self.creator.as_ref(),
+            self.token_account.as_ref(),
+            std::slice::from_ref(&self.authority_bump),
+        ]
+    }
+
+    /// Address of the escrow vault, from the stored bump.  Only meaningful
+    /// for escrowed orders.
+    pub fn vault_address(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(
+            &[
+                b"vault",
+                self.creator.as_ref(),
+                self.token_mint.as_ref(),
+                &self.order_id.to_le_bytes(),
+                &[self.vault_bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| ProgramError::InvalidSeeds.into())
+    }
+
+    /// Address that receives the sale proceeds.
+    pub fn proceeds_recipient(&self) -> Pubkey {
+        self.payout.unwrap_or(self.seller)
+    }
+
+    /// Reject buyers not permitted by `allowed_buyer` or the whitelist.
+    pub fn validate_buyer(&self, buyer: &Pubkey, whitelist: Option<&Whitelist>) -> Result<()> {
+        if let Some(allowed_buyer) = self.allowed_buyer {
+            require_keys_eq!(*buyer, allowed_buyer, SellError::BuyerNotAuthorized);
+        }
+        if self.whitelisted {
+            let whitelist = whitelist.ok_or(SellError::BuyerNotAuthorized)?;
+            require!(
+                whitelist.buyers.contains(buyer),
+                SellError::BuyerNotAuthorized
+            );
+        }
+        Ok(())
+    }
+
+    /// Reject withdrawing liquidity before the deadline from an order the
+    /// seller committed to keep open.
+    pub fn require_cancellable(&self, now: i64) -> Result<()> {
+        require!(
+            self.cancellable_before_deadline || now > self.deadline || self.fills_capped(),
+            SellError::CancelLockedUntilDeadline
+        );
+        Ok(())
+    }
+
+    /// Effective per‑token price at `now`.  Dutch auctions decline linearly
+    /// from `start_price` at go‑live to `end_price` at `deadline`; the
+    /// accrued discount is floored, so in‑between prices round up in the
+    /// seller’s favour, and the price never drops below `reserve_price`.
+    /// In the last `closeout_window` seconds before the deadline
+    /// `closeout_bps` comes off (the discount floored, like the Dutch
+    /// drop).  Fills in the grace period after the deadline pay
+    /// `grace_premium_bps` on top.
+    pub fn price_at(&self, now: i64) -> Result<u64> {
+        let price = match self.pricing_mode {
+            PricingMode::Fixed => self.price_per_token,
+            // needs the oracle account, see `oracle_lamport_price`
+            PricingMode::UsdOracle => return err!(SellError::InvalidPricingMode),
+            // sold through `settle_auction` at the high bid
+            PricingMode::EnglishAuction => return err!(SellError::InvalidPricingMode),
+            PricingMode::DutchAuction => {
+                let start = self.created_at.max(self.start_time);
+                let duration = (self.deadline - start).max(1) as u128;
+                let elapsed = (now - start).clamp(0, self.deadline - start) as u128;
+                let drop = (self.start_price - self.end_price) as u128 * elapsed / duration;
+                let price = u64::try_from(self.start_price as u128 - drop)
+                    .map_err(|_| SellError::MathOverflow)?;
+                price.max(self.reserve_price)
+            }
+        };
+        if now <= self.deadline {
+            // the window is the `closeout_window` seconds ending at the deadline
+            if self.closeout_window == 0 || now <= self.deadline - self.closeout_window {
+                return Ok(price);
+            }
+            let discount = price as u128 * self.closeout_bps as u128 / BPS_DENOMINATOR as u128;
+            return Ok((price - discount as u64).max(self.reserve_price));
+        }
+        // grace window: the deadline price plus the premium, rounded up
+        price
+            .checked_add(fee_for(price, self.grace_premium_bps)?)
+            .ok_or_else(|| SellError::MathOverflow.into())
+    }
+
+    /// Emit `OrderExpiringSoon` if `now` is inside the reminder window and
+    /// it has not been emitted for the current deadline yet.
+    pub fn remind(&mut self, key: Pubkey, now: i64) {
+        if self.reminder_window == 0
+            || self.reminder_sent
+            || now < self.deadline - self.reminder_window
+            || now > self.deadline
+        {
+            return;
+        }
+        self.reminder_sent = true;
+        emit!(OrderExpiringSoon {
+            sell_order: key,
+            seller: self.seller,
+            deadline: self.deadline,
+            seconds_left: self.deadline - now,
+        });
+    }
+
+    /// Last moment the order can be filled: the deadline plus any grace
+    /// period.
+    pub fn closes_at(&self) -> i64 {
+        self.deadline.saturating_add(self.grace_period)
+    }
+}
+
+/// Buyers allowed to fill a whitelisted order (PDA seeded by
+/// `b"whitelist"` + order)
+#[account]
+pub struct Whitelist {
+    pub sell_order: Pubkey,
+    pub buyers: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl Whitelist {
+    // 32 + (4 + 32*MAX_WHITELIST) + 1 (discriminator added at `init`)
+    pub const SIZE: usize = 32 + (4 + 32 * MAX_WHITELIST) + 1;
+}
+
+/// Tokens a buyer has taken from an order and the id of their last
+/// request (PDA seeded by `b"purchase"` + order + buyer)
+#[account]
+pub struct PurchaseRecord {
+    pub sell_order: Pubkey,
+    pub buyer: Pubkey,
+    pub purchased: u64,
+    pub bump: u8,
+    pub last_request_id: u64,
+}
+
+impl PurchaseRecord {
+    pub const SIZE: usize = 32 + 32 + 8 + 1 + 8;
+}
+
+/// Most recent fills of an order, oldest overwritten first (PDA seeded by
+/// `b"fill_feed"` + order).  `next` is the slot the next fill goes to once
+/// the feed is full.
+#[account]
+pub struct FillFeed {
+    pub sell_order: Pubkey,
+    pub fills: Vec<FillEntry>,
+    pub next: u8,
+    pub bump: u8,
+}
+
+impl FillFeed {
+    pub const SIZE: usize = 32 + (4 + FillEntry::SIZE * FILL_FEED_LEN) + 1 + 1;
+
+    /// Append `entry`, overwriting the oldest fill when full.
+    pub fn record(&mut self, entry: FillEntry) {
+        if self.fills.len() < FILL_FEED_LEN {
+            self.fills.push(entry);
+        } else {
+            self.fills[self.next as usize] = entry;
+        }
+        self.next = ((self.next as usize + 1) % FILL_FEED_LEN) as u8;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FillEntry {
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub price_per_token: u64,
+    pub timestamp: i64,
+}
+
+impl FillEntry {
+    pub const SIZE: usize = 32 + 8 + 8 + 8;
+}
+
+/// Lamports a buyer escrows for `amount` tokens of an order at
+/// `price_per_token` (PDA seeded by `b"bid"` + order + bidder)
+#[account]
+pub struct Bid {
+    pub bidder: Pubkey,
+    pub sell_order: Pubkey,
+    pub amount: u64,
+    pub price_per_token: u64,
+    pub bump: u8,
+}
+
+impl Bid {
+    pub const SIZE: usize = 32 + 32 + 8 + 8 + 1;
+}
+
+/// Live orders of one seller (PDA seeded by `b"seller_index"` + seller)
+#[account]
+pub struct SellerIndex {
+    pub seller: Pubkey,
+    pub orders: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl SellerIndex {
+    pub const SIZE: usize = 32 + (4 + 32 * MAX_SELLER_ORDERS) + 1;
+}
+
+/// Live orders of one mint (PDA seeded by `b"mint_index"` + mint)
+#[account]
+pub struct MintIndex {
+    pub mint: Pubkey,
+    pub orders: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl MintIndex {
+    pub const SIZE: usize = 32 + (4 + 32 * MAX_MINT_ORDERS) + 1;
+}
+
+/// Mints the admin allows to be listed (PDA seeded by `b"mint_allowlist"`)
+#[account]
+pub struct MintAllowlist {
+    pub mints: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl MintAllowlist {
+    pub const SIZE: usize = (4 + 32 * MAX_ALLOWED_MINTS) + 1;
+}
+
+/// Seller’s share of one fill of a `release_delay` order, held until
+/// `release_payment` (PDA seeded by `b"receipt"` + order + its
+/// `created_at` + fill index)
+#[account]
+pub struct PaymentReceipt {
+    pub sell_order: Pubkey,
+    /// `created_at` of the order, telling apart orders reopened at the
+    /// same address
+    pub order_created_at: i64,
+    pub buyer: Pubkey,
+    /// `receipts_issued` of the order before this fill
+    pub fill_index: u32,
+    /// The order’s proceeds recipient at the time of the fill
+    pub payee: Pubkey,
+    /// Lamports held for `payee`, on top of the receipt’s rent
+    pub amount: u64,
+    /// From then on anyone may release the payment
+    pub release_at: i64,
+    pub bump: u8,
+}
+
+impl PaymentReceipt {
+    pub const SIZE: usize = 32 + 8 + 32 + 4 + 32 + 8 + 8 + 1;
+}
+
+/// Buyer trading without the protocol fee (PDA seeded by
+/// `b"fee_exemption"` + buyer)
+#[account]
+pub struct FeeExemption {
+    pub buyer: Pubkey,
+    pub bump: u8,
+}
+
+impl FeeExemption {
+    pub const SIZE: usize = 32 + 1;
+}
+
+/// Global program settings (PDA seeded by `b"config"`)
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    /// Protocol fee taken from every purchase, in basis points
+    pub fee_bps: u16,
+    /// Emergency stop for order creation and purchases
+    pub paused: bool,
+    pub bump: u8,
+    /// Share of the protocol fee paid to a `buy` referrer, in basis points
+    /// of the fee
+    pub referral_bps: u16,
+    /// Seconds an order must wait between `update_price` calls
+    pub price_update_cooldown: i64,
+}
+
+impl Config {
+    // 32*2 + 2 + 1 + 1 + 2 + 8 = 78 (discriminator added at `init`)
+    pub const SIZE: usize = 78;
+}
+
+/// Layout v1: the first deployed layout, at
+/// `[b"sell_order", seller, token_account]`.  `bump` is the order
+/// authority’s.  Only read by `migrate_order`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SellOrderV1 {
+    pub seller: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub amount_remaining: u64,
+    pub price_per_token: u64,
+    pub deadline: i64,
+    pub bump: u8,
+}
+
+impl SellOrderV1 {
+    // v1 counted the discriminator in its SIZE and still allocated
+    // 8 + SIZE, so its accounts end in 8 unused bytes:
+    // 8 + 32*3 + 8*3 + 1 = 129
+    pub const SIZE: usize = 129;
+}
+
+impl From<SellOrderV1> for SellOrder {
+    fn from(v1: SellOrderV1) -> Self {
+        Self {
+            version: SellOrder::VERSION,
+            // set by `migrate_order`, as are `token_decimals`,
+            // `order_bump` and the timestamps
+            order_id: 0,
+            seller: v1.seller,
+            token_mint: v1.token_mint,
+            token_account: v1.token_account,
+            amount_remaining: v1.amount_remaining,
+            price_per_token: v1.price_per_token,
+            deadline: v1.deadline,
+            payment_mint: None,
+            min_purchase: 0,
+            escrowed: false,
+            allowed_buyer: None,
+            payout: None,
+            whitelisted: false,
+            pricing_mode: PricingMode::Fixed,
+            start_price: v1.price_per_token,
+            end_price: v1.price_per_token,
+            created_at: 0,
+            authority_bump: v1.bump,
+            order_bump: 0,
+            total_filled: 0,
+            num_fills: 0,
+            escrow_proceeds: false,
+            proceeds_collected: 0,
+            token_decimals: 0,
+            creator: v1.seller,
+            max_per_buyer: 0,
+            tiers: Vec::new(),
+            start_time: 0,
+            status: OrderStatus::Active,
+            vault_bump: 0,
+            is_nft: false,
+            royalty_bps: 0,
+            royalty_creator: Pubkey::default(),
+            cumulative_price_time: 0,
+            last_fill_time: 0,
+            cancellable_before_deadline: true,
+            fill_mode: FillMode::Partial,
+            reserve_price: 0,
+            grace_period: 0,
+            grace_premium_bps: 0,
+            sol_price_per_token: 0,
+            tick_size: 0,
+            usd_price_per_token: 0,
+            oracle: Pubkey::default(),
+            price_step_bps: 0,
+            price_step_amount: 0,
+            dust_threshold: 0,
+            sources: Vec::new(),
+            closeout_window: 0,
+            closeout_bps: 0,
+            records_fills: false,
+            paused: false,
+            reminder_window: 0,
+            reminder_sent: false,
+            cancel_authority: None,
+            label: [0; 32],
+            high_bid: 0,
+            high_bidder: None,
+            callback_program: None,
+            callback_required: false,
+            percentage_of_balance: None,
+            last_price_update: 0,
+            release_delay: 0,
+            max_fills: 0,
+            receipts_issued: 0,
+        }
+    }
+}
+
+// ============================================================================
+// Pricing
+// ============================================================================
+/// Breakdown of what a purchase costs the buyer and where the money goes
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quote {
+    /// Tokens debited from the order
+    pub amount: u64,
+    /// Tokens the buyer must receive, net of any Token‑2022 transfer fee
+    pub net_amount: u64,
+    /// Effective per‑token price at the quoted time
+    pub price_per_token: u64,
+    /// `amount * price_per_token`
+    pub subtotal: u64,
+    /// Protocol fee, carved out of the seller’s proceeds
+    pub fee: u64,
+    /// Creator royalty, carved out of the seller’s proceeds
+    pub royalty: u64,
+    /// Everything the buyer pays; the seller receives `total - fee - royalty`
+    pub total: u64,
+}
+
+/// Instruction data of a `callback_program` call, after
+/// `FILL_CALLBACK_DISCRIMINATOR`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FillCallback {
+    pub sell_order: Pubkey,
+    pub buyer: Pubkey,
+    /// Tokens the buyer received
+    pub amount: u64,
+    pub price_per_token: u64,
+    /// Lamports the buyer paid
+    pub total: u64,
+    /// 0 when this fill sold the order out
+    pub remaining_after: u64,
+}
+
+impl Quote {
+    /// What the seller (or payout address) receives.
+    pub fn proceeds(&self) -> Result<u64> {
+        self.total
+            .checked_sub(self.fee)
+            .and_then(|rest| rest.checked_sub(self.royalty))
+            .ok_or_else(|| SellError::MathOverflow.into())
+    }
+}
+
+/// Price `amount` tokens of `order` at `now` with the given protocol fee.
+/// Every purchase path settles through this so clients and on‑chain code
+/// share one source of truth.
+pub fn quote(order: &SellOrder, amount: u64, fee_bps: u16, now: i64) -> Result<Quote> {
+    quote_at(order, amount, order.price_for(amount, now)?, fee_bps)
+}
+
+/// [`quote`] at an explicit `price_per_token`.
+pub fn quote_at(
+    order: &SellOrder,
+    amount: u64,
+    price_per_token: u64,
+    fee_bps: u16,
+) -> Result<Quote> {
+    let (subtotal, _, fee, royalty) =
+        compute_totals(amount, price_per_token, fee_bps, order.royalty_bps)?;
+
+    Ok(Quote {
+        amount,
+        net_amount: amount,
+        price_per_token,
+        subtotal,
+        fee,
+        royalty,
+        total: subtotal,
+    })
+}
+
+/// All payment maths of a fill of `amount` at `price_per_token`: returns
+/// `(total, seller, fee, royalty)` where the last three are the
+/// [`split_payment`] of `total`.  Intermediates are u128 and every step
+/// that does not fit back into a u64 fails with `MathOverflow`.
+pub fn compute_totals(
+    amount: u64,
+    price_per_token: u64,
+    fee_bps: u16,
+    royalty_bps: u16,
+) -> Result<(u64, u64, u64, u64)> {
+    let total = u64::try_from(amount as u128 * price_per_token as u128)
+        .map_err(|_| SellError::MathOverflow)?;
+    let (seller, fee, royalty) = split_payment(total, fee_bps, royalty_bps)?;
+    Ok((total, seller, fee, royalty))
+}
+
+/// Split `total` into `(seller, fee, royalty)`, which always sum to exactly
+/// `total`.  The fee rounds up, the royalty rounds down and is cut back if
+/// both together would exceed `total`; the seller takes what is left.
+pub fn split_payment(total: u64, fee_bps: u16, royalty_bps: u16) -> Result<(u64, u64, u64)> {
+    let fee = fee_for(total, fee_bps)?.min(total);
+    let royalty = royalty_for(total, royalty_bps)?.min(total - fee);
+    Ok((total - fee - royalty, fee, royalty))
+}
+
+/// Whether `price` sits on a grid of `tick_size`; 0 disables the grid.
+pub fn on_tick(price: u64, tick_size: u64) -> bool {
+    tick_size == 0 || price % tick_size == 0
+}
+
+/// Protocol fee owed on `total`.  Rounds up so that small trades cannot
+/// dodge the fee entirely.
+pub fn fee_for(total: u64, fee_bps: u16) -> Result<u64> {
+    let fee =
+        (total as u128 * fee_bps as u128 + (BPS_DENOMINATOR - 1) as u128) / BPS_DENOMINATOR as u128;
+    u64::try_from(fee).map_err(|_| SellError::MathOverflow.into())
+}
+
+/// Lamports per token for an order priced at `usd_price_per_token`, at the
+/// SOL/USD rate of `oracle`.  Rounds up in the seller’s favour; prices older
+/// than `MAX_ORACLE_AGE` are rejected.
+pub fn oracle_lamport_price(
+    oracle: &AccountInfo,
+    usd_price_per_token: u64,
+    now: i64,
+) -> Result<u64> {
+    let feed = load_price_feed_from_account_info(oracle).map_err(|_| SellError::InvalidOracle)?;
+    let price = feed
+        .get_price_no_older_than(now, MAX_ORACLE_AGE)
+        .ok_or(SellError::OraclePriceStale)?;
+    require!(price.price > 0, SellError::InvalidOracle);
+
+    // lamports = usd × 10^(9 − USD_DECIMALS) / (price × 10^expo)
+    let scale = 9 - USD_DECIMALS as i32 - price.expo;
+    let pow = 10u128
+        .checked_pow(scale.unsigned_abs())
+        .ok_or(SellError::MathOverflow)?;
+    let (numerator, denominator) = if scale >= 0 {
+        (usd_price_per_token as u128 * pow, price.price as u128)
+    } else {
+        (usd_price_per_token as u128, price.price as u128 * pow)
+    };
+    let lamports = numerator.div_ceil(denominator);
+    u64::try_from(lamports).map_err(|_| SellError::MathOverflow.into())
+}
+
+/// Referrer's cut of the protocol `fee`.  Rounds down, leaving the
+/// remainder to the treasury.
+pub fn referral_for(fee: u64, referral_bps: u16) -> Result<u64> {
+    let referral = fee as u128 * referral_bps as u128 / BPS_DENOMINATOR as u128;
+    u64::try_from(referral).map_err(|_| SellError::MathOverflow.into())
+}
+
+/// Creator royalty owed on `total`.  Rounds down, leaving the remainder to
+/// the seller.
+pub fn royalty_for(total: u64, royalty_bps: u16) -> Result<u64> {
+    let royalty = total as u128 * royalty_bps as u128 / BPS_DENOMINATOR as u128;
+    u64::try_from(royalty).map_err(|_| SellError::MathOverflow.into())
+}
+
+/// Convert `ui_amount` whole tokens into base units of a mint with
+/// `decimals`.
+pub fn to_base_units(ui_amount: u64, decimals: u8) -> Result<u64> {
+    10u64
+        .checked_pow(decimals as u32)
+        .and_then(|unit| ui_amount.checked_mul(unit))
+        .ok_or_else(|| SellError::MathOverflow.into())
+}
+
+/// Split `amount` base units into whole tokens and the fractional remainder
+/// (still in base units).
+pub fn to_ui_amount(amount: u64, decimals: u8) -> Result<(u64, u64)> {
+    let unit = 10u64
+        .checked_pow(decimals as u32)
+        .ok_or(SellError::MathOverflow)?;
+    Ok((amount / unit, amount % unit))
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+#[event]
+pub struct SellOrderCreated {
+    pub sell_order: Pubkey,
+    pub seller: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    /// Base units of the payment asset per base unit of `token_mint`
+    pub price_per_token: u64,
+    /// `token_mint` decimals, for rendering `amount` and `price_per_token`
+    pub decimals: u8,
+    pub deadline: i64,
+    pub payment_mint: Option<Pubkey>,
+    pub created_at: i64,
+    pub label: [u8; 32],
+}
+
+#[event]
+pub struct PaymentReleased {
+    pub sell_order: Pubkey,
+    pub buyer: Pubkey,
+    pub payee: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct AuctionBidPlaced {
+    pub sell_order: Pubkey,
+    pub bidder: Pubkey,
+    pub bid: u64,
+}
+
+#[event]
+pub struct OrderExpiringSoon {
+    pub sell_order: Pubkey,
+    pub seller: Pubkey,
+    pub deadline: i64,
+    pub seconds_left: i64,
+}
+
+/// Emitted by `health_check` for orders that can no longer be filled in full
+#[event]
+pub struct OrderUnderfunded {
+    pub sell_order: Pubkey,
+    pub seller: Pubkey,
+    pub amount_remaining: u64,
+    pub delegated_amount: u64,
+    pub balance: u64,
+}
+
+/// Emitted on every fill, so indexers can rebuild fill history from logs
+#[event]
+pub struct OrderFilled {
+    pub sell_order: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub token_mint: Pubkey,
+    pub amount_filled: u64,
+    pub price_per_token: u64,
+    /// Total paid by the buyer for this fill
+    pub price_paid: u64,
+    pub remaining_after: u64,
+    /// Seller side of the trade
+    pub maker: Pubkey,
+    /// Buyer side of the trade
+    pub taker: Pubkey,
+    /// Token base units taken from the order
+    pub notional_tokens: u64,
+    /// What the buyer actually paid, in lamports or `payment_mint` base units
+    pub notional_payment: u64,
+    /// `None` for lamport payments
+    pub payment_mint: Option<Pubkey>,
+}
+
+#[event]
+pub struct PriceUpdated {
+    pub sell_order: Pubkey,
+    pub old_price_per_token: u64,
+    pub new_price_per_token: u64,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub sell_order: Pubkey,
+    pub seller: Pubkey,
+    pub token_mint: Pubkey,
+    pub remaining: u64,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+/// Pay `amount` lamports from `from`.  The payer is owned by the System
+/// Program, so the debit has to go through a CPI.
+fn transfer_lamports<'info>(
+    system_program: &Program<'info, System>,
+    from: &Signer<'info>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    require!(from.lamports() >= amount, SellError::InsufficientFunds);
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: from.to_account_info(),
+                to,
+            },
+        ),
+        amount,
+    )
+}
+
+/// Pay `amount` payment‑mint tokens from the buyer’s account.
+fn transfer_payment_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    authority: &Signer<'info>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    require!(from.amount >= amount, SellError::InsufficientFunds);
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: authority.to_account_info(),
+            },
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+/// Allowance currently granted to `order_authority` on `token_account`.
+fn delegated_to(token_account: &TokenAccount, order_authority: &SystemAccount) -> u64 {
+    if token_account.delegate == COption::Some(order_authority.key()) {
+        token_account.delegated_amount
+    } else {
+        0
+    }
+}
+
+/// Debug‑build checks on state the program trusts without re‑deriving it on
+/// every path, such as stored bumps.  They compile out of release
+/// (deployed) builds and exist to trip in local validator and test runs.
+mod invariants {
+    use super::*;
+
+    /// The stored bumps reproduce the order's own PDAs.
+    pub fn order_pdas(order: &Account<SellOrder>, order_authority: &Pubkey) {
+        debug_assert_eq!(order.order_address().ok(), Some(order.key()), "order_bump");
+        debug_assert_eq!(
+            order.authority_address().ok(),
+            Some(*order_authority),
+            "authority_bump"
+        );
+        if order.escrowed {
+            debug_assert_eq!(
+                order.vault_address().ok(),
+                Some(order.token_account),
+                "vault_bump"
+            );
+        }
+    }
+
+    /// A delegated order without extra sources is backed by the allowance
+    /// just approved for all of it on its token account.  Only call this
+    /// right after such an approval: elsewhere the seller may have revoked
+    /// or spent the allowance, which is a valid state.  The account is
+    /// reloaded, as the approval CPI does not update the deserialized copy.
+    pub fn covered<'info>(
+        order: &SellOrder,
+        token_account: &InterfaceAccount<'info, TokenAccount>,
+        order_authority: &SystemAccount<'info>,
+    ) {
+        // skip the reload where the assertion compiles out
+        if !cfg!(debug_assertions) || order.escrowed || !order.sources.is_empty() {
+            return;
+        }
+        let mut token_account = token_account.clone();
+        token_account.reload().expect("order token account reloads");
+        debug_assert!(
+            backed(order, delegated_to(&token_account, order_authority)),
+            "allowance"
+        );
+    }
+
+    /// `order` is fully backed by an allowance of `delegated` tokens.
+    pub fn backed(order: &SellOrder, delegated: u64) -> bool {
+        order.amount_remaining <= delegated
+    }
+}
+
+/// (Re)approve the order authority PDA as delegate for exactly `amount`
+/// tokens of the seller’s account.
+fn approve_delegate<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    token_account: &InterfaceAccount<'info, TokenAccount>,
+    order_authority: &SystemAccount<'info>,
+    seller: &Signer<'info>,
+    amount: u64,
+) -> Result<()> {
+    token_interface::approve(
+        CpiContext::new(
+            token_program.to_account_info(),
+            Approve {
+                to: token_account.to_account_info(),
+                delegate: order_authority.to_account_info(),
+                authority: seller.to_account_info(),
+            },
+        ),
+        amount,
+    )
+}
+
+/// Withdraw a cancelled order’s `amount` from the shared delegate allowance,
+/// revoking the delegate once no other order on the token account needs it.
+fn release_allowance<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    token_account: &InterfaceAccount<'info, TokenAccount>,
+    order_authority: &SystemAccount<'info>,
+    seller: &Signer<'info>,
+    amount: u64,
+) -> Result<()> {
+    // revoked (or re‑delegated elsewhere) outside this program: nothing of
+    // ours to withdraw, and revoking would clobber someone else’s delegate
+    if token_account.delegate != COption::Some(order_authority.key()) {
+        return Ok(());
+    }
+    let allowance = delegated_to(token_account, order_authority).saturating_sub(amount);
+    if allowance > 0 {
+        return approve_delegate(
+            token_program,
+            token_account,
+            order_authority,
+            seller,
+            allowance,
+        );
+    }
+    token_interface::revoke(CpiContext::new(
+        token_program.to_account_info(),
+        Revoke {
+            source: token_account.to_account_info(),
+            authority: seller.to_account_info(),
+        },
+    ))
+}
+
+/// Clean up an order whose `amount_remaining` reached zero, returning all
+/// rent to the seller.  SPL Token clears the delegate once `delegated_amount`
+/// hits zero, so delegated orders only need the order account closed;
+/// escrow orders also close their (now empty) vault.  Orders holding
+/// escrowed proceeds stay open until `withdraw_proceeds`.
+///
+/// Anyone can send tokens to a vault, and closing one that is not empty
+/// fails.  Rather than let a stray deposit revert the last fill, such an
+/// order is left open with its vault for the seller to `reclaim`.
+fn close_sold_out_order<'info>(
+    order: &Account<'info, SellOrder>,
+    token_program: &Interface<'info, TokenInterface>,
+    token_account: &InterfaceAccount<'info, TokenAccount>,
+    order_authority: &SystemAccount<'info>,
+    seller: AccountInfo<'info>,
+) -> Result<()> {
+    if order.escrowed {
+        let mut vault = token_account.clone();
+        vault.reload()?;
+        if vault.amount > 0 {
+            return Ok(());
+        }
+        close_vault(
+            token_program,
+            token_account,
+            order_authority,
+            seller.clone(),
+            order,
+        )?;
+    }
+    if order.escrow_proceeds {
+        return Ok(());
+    }
+    order.close(seller)
+}
+
+/// Turn a `percentage_of_balance` listing into a concrete `amount` out of
+/// `available` tokens, rounding down.
+fn size_from_balance(params: &mut OrderParams, available: u64) -> Result<()> {
+    let Some(bps) = params.percentage_of_balance else {
+        return Ok(());
+    };
+    require!(
+        bps > 0 && bps <= MAX_FEE_BPS && params.amount == 0,
+        SellError::InvalidPercentage
+    );
+    // at most `available`, so it fits back into a u64
+    params.amount = (available as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    require!(params.amount > 0, SellError::InvalidAmount);
+    Ok(())
+}
+
+/// An order flagged `is_nft` must sell the entire supply of a 0‑decimal
+/// mint whose supply is one.
+fn validate_nft(params: &OrderParams, mint: &Mint) -> Result<()> {
+    if params.is_nft {
+        require!(
+            params.amount == 1 && mint.decimals == 0 && mint.supply == 1,
+            SellError::NotAnNft
+        );
+    }
+    Ok(())
+}
+
+/// Bounded list of live order PDAs kept for discovery
+trait OrderIndex {
+    const MAX_ORDERS: usize;
+    fn orders(&mut self) -> &mut Vec<Pubkey>;
+    /// `order` belongs in this index
+    fn lists(&self, order: &SellOrder) -> bool;
+    /// Outcome of adding to a full index
+    fn full() -> Result<()>;
+}
+
+impl OrderIndex for SellerIndex {
+    const MAX_ORDERS: usize = MAX_SELLER_ORDERS;
+    fn orders(&mut self) -> &mut Vec<Pubkey> {
+        &mut self.orders
+    }
+    fn lists(&self, order: &SellOrder) -> bool {
+        order.seller == self.seller
+    }
+    fn full() -> Result<()> {
+        err!(SellError::SellerIndexFull)
+    }
+}
+
+impl OrderIndex for MintIndex {
+    const MAX_ORDERS: usize = MAX_MINT_ORDERS;
+    fn orders(&mut self) -> &mut Vec<Pubkey> {
+        &mut self.orders
+    }
+    fn lists(&self, order: &SellOrder) -> bool {
+        order.token_mint == self.mint
+    }
+    /// Anyone can list a mint, so a full index skips the order instead of
+    /// blocking further listings
+    fn full() -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Record `order` in a seller or mint index, if one was passed.
+fn index_add<I: OrderIndex>(index: Option<&mut I>, order: Pubkey) -> Result<()> {
+    if let Some(index) = index {
+        let orders = index.orders();
+        if !orders.contains(&order) {
+            if orders.len() >= I::MAX_ORDERS {
+                return I::full();
+            }
+            orders.push(order);
+        }
+    }
+    Ok(())
+}
+
+/// Drop `order` from a seller or mint index, if one was passed, handing the
+/// index back for chaining.
+fn index_remove<'a, I: OrderIndex>(
+    mut index: Option<&'a mut I>,
+    order: &Pubkey,
+) -> Option<&'a mut I> {
+    if let Some(index) = index.as_mut() {
+        index.orders().retain(|o| o != order);
+    }
+    index
+}
+
+/// Drop `order` from a seller or mint index passed alongside orders of any
+/// seller or mint, if it is that order's index.
+fn index_remove_listed<I: OrderIndex>(index: Option<&mut I>, order: &Account<SellOrder>) {
+    if let Some(index) = index.filter(|index| index.lists(order)) {
+        index.orders().retain(|o| *o != order.key());
+    }
+}
+
+/// Listing is permissionless until the admin creates the mint allowlist;
+/// from then on `mint` must be on it.
+fn check_mint_allowed(mint_allowlist: &UncheckedAccount, mint: &Pubkey) -> Result<()> {
+    if mint_allowlist.data_is_empty() {
+        return Ok(());
+    }
+    let allowlist = Account::<MintAllowlist>::try_from(mint_allowlist)?;
+    require!(allowlist.mints.contains(mint), SellError::MintNotAllowed);
+    Ok(())
+}
+
+/// Move lamports out of an account owned by this program.
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let mut from_lamports = from.try_borrow_mut_lamports()?;
+    let mut to_lamports = to.try_borrow_mut_lamports()?;
+    **from_lamports = from_lamports
+        .checked_sub(amount)
+        .ok_or(SellError::InsufficientFunds)?;
+    **to_lamports = to_lamports
+        .checked_add(amount)
+        .ok_or(SellError::MathOverflow)?;
+    Ok(())
+}
+
+/// Close an empty escrow vault, signed by the order authority PDA.
+fn close_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    order_authority: &SystemAccount<'info>,
+    destination: AccountInfo<'info>,
+    order: &SellOrder,
+) -> Result<()> {
+    let seeds: &[&[&[u8]]] = &[&order.authority_seeds()];
+
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: vault.to_account_info(),
+            destination,
+            authority: order_authority.to_account_info(),
+        },
+        seeds,
+    ))
+}
+
+/// How much a lamport buy takes from the order
+enum BuySize {
+    /// Exactly this many tokens delivered to the buyer
+    Net(u64),
+    /// Everything left on the order
+    All,
+    /// As many tokens as this many lamports pay for
+    Budget(u64),
+    /// This many tokens, or whatever is left if less
+    UpTo(u64),
+}
+
+/// Shared body of `buy`, `buy_all`, `buy_up_to` and `buy_exact_cost`: price a lamport
+/// fill of `size`, collect payment and settle.
+fn buy_with_lamports<'info>(
+    accounts: &mut Buy<'info>,
+    sources: &[AccountInfo<'info>],
+    size: BuySize,
+    max_price_per_token: u64,
+    max_total_cost: u64,
+    request_id: Option<u64>,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+
+    // token‑priced orders may also take lamports at `sol_price_per_token`;
+    // USD orders are converted at the oracle rate
+    let price_override = match accounts.sell_order.payment_mint {
+        None if accounts.sell_order.pricing_mode == PricingMode::UsdOracle => {
+            let oracle = accounts.oracle.as_ref().ok_or(SellError::InvalidOracle)?;
+            Some(oracle_lamport_price(
+                oracle,
+                accounts.sell_order.usd_price_per_token,
+                now,
+            )?)
+        }
+        None => None,
+        Some(_) => {
+            require!(
+                accounts.sell_order.sol_price_per_token > 0,
+                SellError::WrongPaymentMethod
+            );
+            Some(accounts.sell_order.sol_price_per_token)
+        }
+    };
+
+    // held payments wait in a receipt of this fill until `release_payment`
+    let release_delay = accounts.sell_order.release_delay;
+    require!(
+        (release_delay > 0) == accounts.payment_receipt.is_some(),
+        SellError::InvalidReceipt
+    );
+    let fill_index = accounts.sell_order.receipts_issued;
+    if release_delay > 0 {
+        accounts.sell_order.receipts_issued =
+            fill_index.checked_add(1).ok_or(SellError::MathOverflow)?;
+    }
+
+    // escrowed proceeds stay on the order account until `withdraw_proceeds`
+    let proceeds = if accounts.sell_order.escrow_proceeds {
+        accounts.sell_order.to_account_info()
+    } else if let Some(receipt) = &accounts.payment_receipt {
+        receipt.to_account_info()
+    } else {
+        accounts.proceeds.to_account_info()
+    };
+
+    let fill = Fill {
+        buyer: accounts.buyer.key(),
+        seller: accounts.seller.to_account_info(),
+        sell_order: &mut accounts.sell_order,
+        token_account: &accounts.token_account,
+        token_mint: &accounts.token_mint,
+        buyer_token_account: &accounts.buyer_token_account,
+        seller_token_account: accounts.seller_token_account.as_ref(),
+        order_authority: &accounts.order_authority,
+        whitelist: accounts.whitelist.as_deref(),
+        fill_feed: accounts.fill_feed.as_deref_mut(),
+        seller_index: accounts.seller_index.as_deref_mut(),
+        mint_index: accounts.mint_index.as_deref_mut(),
+        purchase_record: accounts.purchase_record.as_deref_mut(),
+        request_id,
+        price_override,
+        sources,
+        fee_bps: if accounts.fee_exemption.is_some() {
+            0
+        } else {
+            accounts.config.fee_bps
+        },
+        config: &accounts.config,
+        token_program: &accounts.token_program,
+    };
+    let quote = match size {
+        BuySize::Net(amount) => fill.prepare(amount, max_price_per_token, now)?,
+        BuySize::All => {
+            fill.prepare_gross(fill.sell_order.amount_remaining, max_price_per_token, now)?
+        }
+        BuySize::Budget(max_lamports) => {
+            let amount = fill.affordable(max_lamports, now)?;
+            let quote = fill.prepare_gross(amount, max_price_per_token, now)?;
+            require!(quote.total <= max_lamports, SellError::SlippageExceeded);
+            quote
+        }
+        BuySize::UpTo(max_amount) => {
+            let amount = max_amount.min(fill.sell_order.amount_remaining);
+            fill.prepare_gross(amount, max_price_per_token, now)?
+        }
+    };
+    require!(quote.total <= max_total_cost, SellError::TotalCostExceeded);
+
+    // --- handle payment ------------------------------------------------------
+    transfer_lamports(
+        &accounts.system_program,
+        &accounts.buyer,
+        proceeds,
+        quote.proceeds()?,
+    )?;
+    // a referrer's cut comes out of the protocol fee, never the proceeds
+    let referral = match &accounts.referrer {
+        Some(referrer) => {
+            require_keys_neq!(
+                referrer.key(),
+                accounts.buyer.key(),
+                SellError::InvalidReferrer
+            );
+            let referral = referral_for(quote.fee, accounts.config.referral_bps)?;
+            transfer_lamports(
+                &accounts.system_program,
+                &accounts.buyer,
+                referrer.to_account_info(),
+                referral,
+            )?;
+            referral
+        }
+        None => 0,
+    };
+    transfer_lamports(
+        &accounts.system_program,
+        &accounts.buyer,
+        accounts.treasury.to_account_info(),
+        quote.fee - referral,
+    )?;
+    if quote.royalty > 0 {
+        let creator = accounts
+            .royalty_creator
+            .as_ref()
+            .ok_or(SellError::InvalidRoyalty)?;
+        transfer_lamports(
+            &accounts.system_program,
+            &accounts.buyer,
+            creator.to_account_info(),
+            quote.royalty,
+        )?;
+    }
+
+    fill.settle(&quote, now)?;
+
+    if let Some(receipt) = accounts.payment_receipt.as_deref_mut() {
+        let sell_order = accounts.sell_order.key();
+        let created_at = accounts.sell_order.created_at;
+        let (_, bump) = Pubkey::find_program_address(
+            &[
+                b"receipt",
+                sell_order.as_ref(),
+                &created_at.to_le_bytes(),
+                &fill_index.to_le_bytes(),
+            ],
+            &crate::ID,
+        );
+        receipt.sell_order = sell_order;
+        receipt.order_created_at = created_at;
+        receipt.buyer = accounts.buyer.key();
+        receipt.fill_index = fill_index;
+        receipt.payee = accounts.proceeds.key();
+        receipt.amount = quote.proceeds()?;
+        receipt.release_at = now.saturating_add(release_delay);
+        receipt.bump = bump;
+    }
+    fire_fill_callback(accounts, &quote)
+}
+
+/// CPI the order’s `callback_program` with the details of a settled fill,
+/// see [`buy`].
+fn fire_fill_callback(accounts: &Buy, quote: &Quote) -> Result<()> {
+    let order = &accounts.sell_order;
+    let Some(program) = accounts.callback_program.as_ref() else {
+        require!(!order.callback_required, SellError::CallbackRequired);
+        return Ok(());
+    };
+
+    let mut data = FILL_CALLBACK_DISCRIMINATOR.to_vec();
+    FillCallback {
+        sell_order: order.key(),
+        buyer: accounts.buyer.key(),
+        amount: quote.net_amount,
+        price_per_token: quote.price_per_token,
+        total: quote.total,
+        remaining_after: order.amount_remaining,
+    }
+    .serialize(&mut data)?;
+
+    let mut metas = vec![
+        AccountMeta::new_readonly(order.key(), false),
+        AccountMeta::new_readonly(accounts.buyer_token_account.key(), false),
+    ];
+    let mut infos = vec![
+        order.to_account_info(),
+        accounts.buyer_token_account.to_account_info(),
+    ];
+    if let Some(state) = &accounts.callback_state {
+        metas.push(AccountMeta::new(state.key(), false));
+        infos.push(state.to_account_info());
+    }
+    invoke(
+        &Instruction {
+            program_id: program.key(),
+            accounts: metas,
+            data,
+        },
+        &infos,
+    )
+    .map_err(Into::into)
+}
+
+/// Accounts every purchase path needs, independent of how the buyer pays.
+/// Callers `prepare` the fill, collect payment, then `settle` it.
+struct Fill<'a, 'info> {
+    buyer: Pubkey,
+    seller: AccountInfo<'info>,
+    sell_order: &'a mut Account<'info, SellOrder>,
+    token_account: &'a InterfaceAccount<'info, TokenAccount>,
+    token_mint: &'a InterfaceAccount<'info, Mint>,
+    buyer_token_account: &'a InterfaceAccount<'info, TokenAccount>,
+    /// Gets the rest of an escrow order's vault at its fill cap
+    seller_token_account: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    order_authority: &'a SystemAccount<'info>,
+    whitelist: Option<&'a Whitelist>,
+    fill_feed: Option<&'a mut FillFeed>,
+    seller_index: Option<&'a mut SellerIndex>,
+    mint_index: Option<&'a mut MintIndex>,
+    purchase_record: Option<&'a mut PurchaseRecord>,
+    request_id: Option<u64>,
+    /// Flat per‑token price replacing the order’s own pricing (lamport
+    /// fills of token‑priced orders)
+    price_override: Option<u64>,
+    /// `[token_account, authority]` pairs for the order's `sources`
+    sources: &'a [AccountInfo<'info>],
+    /// Protocol fee rate of this fill: `config.fee_bps`, or 0 for a buyer
+    /// holding a `FeeExemption`
+    fee_bps: u16,
+    config: &'a Config,
+    token_program: &'a Interface<'info, TokenInterface>,
+}
+
+impl<'a, 'info> Fill<'a, 'info> {
+    /// Run every pre‑payment check and price the fill.  `amount` is what
+    /// the buyer receives; on transfer‑fee mints the order is debited (and
+    /// the buyer charged for) the grossed‑up amount.
+    fn prepare(&self, amount: u64, max_price_per_token: u64, now: i64) -> Result<Quote> {
+        let gross = gross_up_transfer_fee(self.token_mint, amount)?;
+        let quote = self.prepare_gross(gross, max_price_per_token, now)?;
+        Ok(Quote {
+            net_amount: amount,
+            ..quote
+        })
+    }
+
+    /// Largest amount (up to `amount_remaining`) whose price at `now` fits
+    /// within `budget` lamports.  Every tier price is tried as well as the
+    /// base price, since a larger fill may qualify for a cheaper tier.
+    fn affordable(&self, budget: u64, now: i64) -> Result<u64> {
+        let order = &self.sell_order;
+        let price_for = |amount| match self.price_override {
+            Some(price_per_token) => Ok(price_per_token),
+            None => order.price_for(amount, now),
+        };
+        let mut prices = vec![price_for(order.amount_remaining)?];
+        if self.price_override.is_none() {
+            prices.push(order.price_at(now)?);
+            prices.extend(order.tiers.iter().map(|tier| tier.price_per_token));
+        }
+
+        let mut best = 0;
+        for price in prices.into_iter().filter(|&price| price > 0) {
+            let amount = (budget / price).min(order.amount_remaining);
+            if amount as u128 * price_for(amount)? as u128 <= budget as u128 {
+                best = best.max(amount);
+            }
+        }
+        Ok(best)
+    }
+
+    /// [`Fill::prepare`] for a `gross` amount debited from the order as is.
+    fn prepare_gross(&self, gross: u64, max_price_per_token: u64, now: i64) -> Result<Quote> {
+        let order = &self.sell_order;
+
+        require!(!self.config.paused, SellError::ProgramPaused);
+        // auctions only sell through `settle_auction`
+        require!(
+            order.pricing_mode != PricingMode::EnglishAuction,
+            SellError::InvalidPricingMode
+        );
+        // a wrong stored bump would otherwise only show up as a failed CPI
+        // signature
+        require!(
+            order.authority_address().ok() == Some(self.order_authority.key()),
+            SellError::InvalidAuthority
+        );
+        order.validate_fill(now, gross)?;
+        order.validate_buyer(&self.buyer, self.whitelist)?;
+        require!(
+            !order.records_fills || self.fill_feed.is_some(),
+            SellError::FillFeedRequired
+        );
+        if order.max_per_buyer > 0 {
+            let record = self
+                .purchase_record
+                .as_deref()
+                .ok_or(SellError::PurchaseRecordRequired)?;
+            let purchased = record
+                .purchased
+                .checked_add(gross)
+                .ok_or(SellError::MathOverflow)?;
+            require!(
+                purchased <= order.max_per_buyer,
+                SellError::PerBuyerCapExceeded
+            );
+        }
+        if let Some(request_id) = self.request_id {
+            let record = self
+                .purchase_record
+                .as_deref()
+                .ok_or(SellError::PurchaseRecordRequired)?;
+            require!(
+                record.last_request_id != request_id,
+                SellError::DuplicateRequest
+            );
+        }
+        require!(
+            self.buyer_token_account.mint == order.token_mint
+                && self.token_mint.decimals == order.token_decimals,
+            SellError::MintMismatch
+        );
+        // delivering to the order's own account would count as a sale
+        // without moving anything
+        require_keys_neq!(
+            self.buyer_token_account.key(),
+            self.token_account.key(),
+            SellError::SelfTrade
+        );
+        if order.sources.is_empty() {
+            // the seller may have revoked or shrunk the allowance outside this
+            // program
+            if !order.escrowed {
+                require!(
+                    delegated_to(self.token_account, self.order_authority) >= gross,
+                    SellError::DelegateRevoked
+                );
+            }
+            // ...or moved the tokens away, which SPL would only report
+            // generically
+            require!(
+                self.token_account.amount >= gross,
+                SellError::SellerBalanceInsufficient
+            );
+        } else {
+            let mut available = drawable(self.token_account, self.order_authority);
+            for (account, authority) in load_sources(order, self.sources)? {
+                available = available.saturating_add(drawable(&account, &authority));
+            }
+            require!(available >= gross, SellError::SellerBalanceInsufficient);
+        }
+
+        let quote = match self.price_override {
+            Some(price_per_token) => quote_at(order, gross, price_per_token, self.fee_bps)?,
+            None => quote(order, gross, self.fee_bps, now)?,
+        };
+        let quote = Quote {
+            net_amount: net_of_transfer_fee(self.token_mint, gross)?,
+            ..quote
+        };
+        require!(
+            quote.price_per_token <= max_price_per_token,
+            SellError::SlippageExceeded
+        );
+        Ok(quote)
+    }
+
+    /// Deliver the tokens and record the fill, closing the order once it is
+    /// sold out.
+    fn settle(self, quote: &Quote, now: i64) -> Result<()> {
+        let order = self.sell_order;
+        let amount = quote.amount;
+        invariants::order_pdas(order, &self.order_authority.key());
+
+        let balance_before = self.buyer_token_account.amount;
+        if order.sources.is_empty() {
+            transfer_order_tokens(
+                self.token_program,
+                self.token_account,
+                self.token_mint,
+                self.buyer_token_account,
+                self.order_authority,
+                order.authority_seeds(),
+                amount,
+            )?;
+        } else {
+            // drain the order's own account first, then each source in turn
+            let own = amount.min(drawable(self.token_account, self.order_authority));
+            if own > 0 {
+                transfer_order_tokens(
+                    self.token_program,
+                    self.token_account,
+                    self.token_mint,
+                    self.buyer_token_account,
+                    self.order_authority,
+                    order.authority_seeds(),
+                    own,
+                )?;
+            }
+            let mut left = amount - own;
+            let legs = load_sources(order, self.sources)?;
+            for ((account, authority), source) in legs.iter().zip(&order.sources) {
+                let take = left.min(drawable(account, authority));
+                if take == 0 {
+                    continue;
+                }
+                transfer_order_tokens(
+                    self.token_program,
+                    account,
+                    self.token_mint,
+                    self.buyer_token_account,
+                    authority,
+                    source.authority_seeds(&order.creator),
+                    take,
+                )?;
+                left -= take;
+            }
+            require!(left == 0, SellError::SellerBalanceInsufficient);
+        }
+        // the buyer has already paid for `net_amount`; payments cannot be
+        // clawed back from the seller, so any shortfall reverts the fill
+        let mut buyer_token_account = self.buyer_token_account.clone();
+        buyer_token_account.reload()?;
+        require!(
+            buyer_token_account.amount.saturating_sub(balance_before) >= quote.net_amount,
+            SellError::DeliveryShortfall
+        );
+
+        // a fill that clears the order skips the bookkeeping of a remainder
+        let sold_out = amount == order.amount_remaining;
+        if sold_out {
+            order.amount_remaining = 0;
+        } else {
+            order.amount_remaining -= amount;
+            // an uneconomic remainder closes the order as if sold out; its
+            // allowance stays on the seller's account until they revoke it
+            let dust = order.amount_remaining;
+            if dust < order.dust_threshold {
+                order.amount_remaining = 0;
+                emit!(OrderCancelled {
+                    sell_order: order.key(),
+                    seller: order.seller,
+                    token_mint: order.token_mint,
+                    remaining: dust,
+                });
+            }
+        }
+        if order.escrowed {
+            check_escrow_sync(self.token_account, order)?;
+        }
+        if let Some(record) = self.purchase_record {
+            record.purchased = record
+                .purchased
+                .checked_add(amount)
+                .ok_or(SellError::MathOverflow)?;
+            if let Some(request_id) = self.request_id {
+                record.last_request_id = request_id;
+            }
+        }
+        let filled_before = order.total_filled;
+        order.total_filled = order
+            .total_filled
+            .checked_add(amount)
+            .ok_or(SellError::MathOverflow)?;
+        order.num_fills = order
+            .num_fills
+            .checked_add(1)
+            .ok_or(SellError::MathOverflow)?;
+        order.accrue_price_time(quote.price_per_token, now)?;
+        if !sold_out {
+            let key = order.key();
+            order.remind(key, now);
+        }
+        if let Some(feed) = self.fill_feed {
+            feed.record(FillEntry {
+                buyer: self.buyer,
+                amount,
+                price_per_token: quote.price_per_token,
+                timestamp: now,
+            });
+        }
+        if let Some(old_price_per_token) = order.step_price(filled_before)? {
+            emit!(PriceUpdated {
+                sell_order: order.key(),
+                old_price_per_token,
+                new_price_per_token: order.price_per_token,
+            });
+        }
+        if order.escrow_proceeds {
+            order.proceeds_collected = order
+                .proceeds_collected
+                .checked_add(quote.proceeds()?)
+                .ok_or(SellError::MathOverflow)?;
+        }
+
+        emit!(OrderFilled {
+            sell_order: order.key(),
+            seller: order.seller,
+            buyer: self.buyer,
+            token_mint: order.token_mint,
+            amount_filled: amount,
+            price_per_token: quote.price_per_token,
+            price_paid: quote.total,
+            remaining_after: order.amount_remaining,
+            maker: order.seller,
+            taker: self.buyer,
+            notional_tokens: amount,
+            notional_payment: quote.total,
+            payment_mint: order.payment_mint,
+        });
+
+        // --- end escrow orders at their fill cap --------------------------------
+        if order.escrowed && order.amount_remaining > 0 && order.fills_capped() {
+            let seller_token_account = self
+                .seller_token_account
+                .ok_or(SellError::SellerTokenAccountRequired)?;
+            // the whole vault, as stray deposits would keep it from closing
+            let mut vault = self.token_account.clone();
+            vault.reload()?;
+            transfer_order_tokens(
+                self.token_program,
+                self.token_account,
+                self.token_mint,
+                seller_token_account,
+                self.order_authority,
+                order.authority_seeds(),
+                vault.amount,
+            )?;
+            emit!(OrderCancelled {
+                sell_order: order.key(),
+                seller: order.seller,
+                token_mint: order.token_mint,
+                remaining: order.amount_remaining,
+            });
+            order.amount_remaining = 0;
+        }
+
+        // --- close fully sold orders -----------------------------------------
+        if order.amount_remaining == 0 {
+            order.status = OrderStatus::Filled;
+            index_remove_listed(self.seller_index, order);
+            index_remove_listed(self.mint_index, order);
+            close_sold_out_order(
+                order,
+                self.token_program,
+                self.token_account,
+                self.order_authority,
+                self.seller,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Tokens that must leave the order for the buyer to receive `net`, grossed
+/// up by the mint’s Token‑2022 transfer fee for the current epoch.
+fn gross_up_transfer_fee(mint: &InterfaceAccount<Mint>, net: u64) -> Result<u64> {
+    match epoch_transfer_fee(mint)? {
+        Some(transfer_fee) => {
+            let fee = transfer_fee
+                .calculate_inverse_fee(net)
+                .ok_or(SellError::TransferFeeUnsupported)?;
+            net.checked_add(fee)
+                .ok_or_else(|| SellError::MathOverflow.into())
+        }
+        None => Ok(net),
+    }
+}
+
+/// Tokens the buyer receives when `gross` leaves the order, after the
+/// mint’s Token‑2022 transfer fee for the current epoch.
+fn net_of_transfer_fee(mint: &InterfaceAccount<Mint>, gross: u64) -> Result<u64> {
+    match epoch_transfer_fee(mint)? {
+        Some(transfer_fee) => {
+            let fee = transfer_fee
+                .calculate_fee(gross)
+                .ok_or(SellError::TransferFeeUnsupported)?;
+            Ok(gross.saturating_sub(fee))
+        }
+        None => Ok(gross),
+    }
+}
+
+/// The mint’s Token‑2022 transfer fee for the current epoch, if it has one.
+fn epoch_transfer_fee(mint: &InterfaceAccount<Mint>) -> Result<Option<TransferFee>> {
+    let info = mint.to_account_info();
+    if *info.owner != token_2022::ID {
+        return Ok(None);
+    }
+
+    let data = info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => Ok(Some(*fee_config.get_epoch_fee(Clock::get()?.epoch))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Escrow invariant: the vault holds at least `amount_remaining` (stray
+/// deposits may push it above).  The vault is reloaded first, since the
+/// caller's copy is stale after a transfer.
+fn check_escrow_sync(vault: &InterfaceAccount<TokenAccount>, order: &SellOrder) -> Result<()> {
+    let mut vault = vault.clone();
+    vault.reload()?;
+    require!(
+        vault.amount >= order.amount_remaining,
+        SellError::EscrowOutOfSync
+    );
+    Ok(())
+}
+
+/// Tokens the authority PDA can move out of `token_account` right now.
+fn drawable(token_account: &TokenAccount, authority: &SystemAccount) -> u64 {
+    delegated_to(token_account, authority).min(token_account.amount)
+}
+
+/// Load the `[token_account, authority]` pairs passed for the order's
+/// `sources`.  Any prefix of the sources may be passed, in order.
+fn load_sources<'info>(
+    order: &SellOrder,
+    accounts: &[AccountInfo<'info>],
+) -> Result<Vec<(InterfaceAccount<'info, TokenAccount>, SystemAccount<'info>)>> {
+    require!(
+        accounts.len() % 2 == 0 && accounts.len() / 2 <= order.sources.len(),
+        SellError::InvalidSources
+    );
+    accounts
+        .chunks(2)
+        .zip(&order.sources)
+        .map(|(pair, source)| {
+            require!(pair[0].is_writable, SellError::InvalidSources);
+            require_keys_eq!(
+                pair[0].key(),
+                source.token_account,
+                SellError::InvalidSources
+            );
+            require_keys_eq!(
+                pair[1].key(),
+                source.authority_address(&order.creator)?,
+                SellError::InvalidSources
+            );
+            Ok((
+                InterfaceAccount::try_from(&pair[0])?,
+                SystemAccount::try_from(&pair[1])?,
+            ))
+        })
+        .collect()
+}
+
+/// Move `amount` order tokens to `to`, signed by the authority PDA whose
+/// `authority_seeds` are given.
+fn transfer_order_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    authority: &SystemAccount<'info>,
+    authority_seeds: [&[u8]; 3],
+    amount: u64,
+) -> Result<()> {
+    let seeds: &[&[&[u8]]] = &[&authority_seeds];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: authority.to_account_info(),
+            },
+            seeds,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// ============================================================================
+// Errors
+// ============================================================================
+#[error_code]
+pub enum SellError {
     #[msg("Amount must be positive")]
     InvalidAmount,
     #[msg("Price must be positive")]
     InvalidPrice,
     #[msg("Deadline must be in the future")]
     DeadlineInPast,
-    #[msg("The sell order has already expired")] 
+    #[msg("Deadline is too far in the future")]
+    DeadlineTooFar,
+    #[msg("The sell order has already expired")]
     OrderExpired,
-    #[msg("Math overflow")] 
+    #[msg("Math overflow")]
     MathOverflow,
-}
\ No newline at end of file
+    #[msg("Buyer token account mint does not match the order mint")]
+    MintMismatch,
+    #[msg("New deadline must be later than the current one")]
+    DeadlineNotExtended,
+    #[msg("Amount is below the order's minimum purchase")]
+    BelowMinimumPurchase,
+    #[msg("Fee exceeds the maximum allowed")]
+    InvalidFee,
+    #[msg("Treasury account does not match the config")]
+    InvalidTreasury,
+    #[msg("Program is paused")]
+    ProgramPaused,
+    #[msg("Seller token account balance is too low")]
+    SellerBalanceInsufficient,
+    #[msg("Escrowed tokens stay locked in the vault until the deadline")]
+    EscrowLocked,
+    #[msg("Order is not an escrow order")]
+    NotEscrowOrder,
+    #[msg("The order deadline has not been reached yet")]
+    DeadlineNotReached,
+    #[msg("Buyer is not authorized to fill this order")]
+    BuyerNotAuthorized,
+    #[msg("Order whitelist is full")]
+    WhitelistFull,
+    #[msg("Order caps purchases per buyer; pass the buyer's purchase record")]
+    PurchaseRecordRequired,
+    #[msg("Purchase would exceed the per-buyer cap")]
+    PerBuyerCapExceeded,
+    #[msg("Operation not supported by this order's pricing mode")]
+    InvalidPricingMode,
+    #[msg("Price tiers must ascend in quantity and not exceed the base price")]
+    InvalidTiers,
+    #[msg("Current price exceeds the buyer's maximum")]
+    SlippageExceeded,
+    #[msg("An order with this id already exists for this token account")]
+    OrderAlreadyExists,
+    #[msg("Seller revoked or reduced the order's token delegation")]
+    DelegateRevoked,
+    #[msg("Proceeds account does not match the order payout address")]
+    InvalidPayout,
+    #[msg("Buyer has insufficient funds")]
+    InsufficientFunds,
+    #[msg("Malformed batch: bad leg count or accounts")]
+    InvalidBatch,
+    #[msg("Order account is already in the current layout")]
+    AlreadyMigrated,
+    #[msg("Order account layout is not recognised")]
+    InvalidOrderLayout,
+    #[msg("Cannot compute the transfer fee for this mint")]
+    TransferFeeUnsupported,
+    #[msg("New seller must own the order's token account")]
+    InvalidNewSeller,
+    #[msg("Order does not escrow its proceeds")]
+    ProceedsNotEscrowed,
+    #[msg("Proceeds are locked until the order sells out or expires")]
+    ProceedsLocked,
+    #[msg("Withdraw escrowed proceeds before closing the order")]
+    ProceedsPending,
+    #[msg("Order does not accept this payment method")]
+    WrongPaymentMethod,
+    #[msg("Payment token account mint does not match the order payment mint")]
+    PaymentMintMismatch,
+    #[msg("Order is not open for buys yet")]
+    OrderNotStarted,
+    #[msg("Start time must be before the deadline")]
+    InvalidStartTime,
+    #[msg("Order is no longer active")]
+    OrderNotActive,
+    #[msg("Mint is not an NFT (supply 1, 0 decimals) or the order is not for exactly one")]
+    NotAnNft,
+    #[msg("Royalties need an NFT order, a valid rate and the creator's account")]
+    InvalidRoyalty,
+    #[msg("This request id was already filled")]
+    DuplicateRequest,
+    #[msg("Order authority does not match the order's PDA")]
+    InvalidAuthority,
+    #[msg("Order cannot be cancelled before its deadline")]
+    CancelLockedUntilDeadline,
+    #[msg("This order can only be bought in full")]
+    MustFillEntireOrder,
+    #[msg("Mint is not on the listing allowlist")]
+    MintNotAllowed,
+    #[msg("Mint allowlist is full")]
+    MintAllowlistFull,
+    #[msg("Grace period or premium out of range")]
+    InvalidGracePeriod,
+    #[msg("Seller index is full; close an order first")]
+    SellerIndexFull,
+    #[msg("Price is not a multiple of the order's tick size")]
+    PriceNotOnTick,
+    #[msg("Buyer received fewer tokens than paid for")]
+    DeliveryShortfall,
+    #[msg("Oracle account is missing or is not the order's price feed")]
+    InvalidOracle,
+    #[msg("Oracle price is older than MAX_ORACLE_AGE")]
+    OraclePriceStale,
+    #[msg("Bid token account does not belong to the bidder")]
+    InvalidBid,
+    #[msg("Buyer token account is the order's own token account")]
+    SelfTrade,
+    #[msg("Deadline must be at least MIN_DURATION from now")]
+    DeadlineTooSoon,
+    #[msg("Price steps need a fixed price, a valid rate and a step amount within MAX_PRICE_STEPS")]
+    InvalidPriceStep,
+    #[msg("Relisted terms must keep the order's order_id")]
+    OrderIdMismatch,
+    #[msg("Token program does not own the mint or token account")]
+    WrongTokenProgram,
+    #[msg("Dust threshold is only supported on delegated orders")]
+    InvalidDustThreshold,
+    #[msg("Source accounts do not match the order's sources")]
+    InvalidSources,
+    #[msg("Order already draws from MAX_SOURCE_ACCOUNTS sources")]
+    SourcesFull,
+    #[msg("Closeout window must be within MAX_DURATION and its discount below 100%")]
+    InvalidCloseout,
+    #[msg("Order records its fills; pass its FillFeed")]
+    FillFeedRequired,
+    #[msg("Order is paused by its seller")]
+    OrderPaused,
+    #[msg("Buyers cannot refer themselves")]
+    InvalidReferrer,
+    #[msg("Reminder window must be within MAX_DURATION")]
+    InvalidReminderWindow,
+    #[msg("buy_via_cpi expects a program-derived buyer")]
+    BuyerNotPda,
+    #[msg("Signer is neither the seller nor the order's cancel authority")]
+    NotCancelAuthority,
+    #[msg("Escrow vault holds less than the order's amount_remaining")]
+    EscrowOutOfSync,
+    #[msg("Auctions must be escrowed, lamport-priced and have no grace period or proceeds escrow")]
+    InvalidAuction,
+    #[msg("Bid must beat the high bid and the opening price")]
+    AuctionBidTooLow,
+    #[msg("Pass the current high bidder so they can be refunded")]
+    InvalidPreviousBidder,
+    #[msg("Auction has no bids to settle")]
+    NoAuctionBids,
+    #[msg("Auction has a high bid; settle it instead")]
+    AuctionPending,
+    #[msg("Callback program is invalid for this order")]
+    InvalidCallback,
+    #[msg("Order requires its callback program on every fill")]
+    CallbackRequired,
+    #[msg("Percentage of balance must be 1-10000 bps with amount 0, at creation only")]
+    InvalidPercentage,
+    #[msg("Price was updated too recently")]
+    PriceUpdateTooSoon,
+    #[msg("Cooldown must be between zero and the maximum order duration")]
+    InvalidCooldown,
+    #[msg("Total cost exceeds the buyer's maximum")]
+    TotalCostExceeded,
+    #[msg("Release delay must be positive, within the maximum duration and on a lamport order")]
+    InvalidReleaseDelay,
+    #[msg("Pass a payment receipt exactly when the order holds payments")]
+    InvalidReceipt,
+    #[msg("Only the buyer can release this payment before its release time")]
+    PaymentLocked,
+    #[msg("Pass the escrow vault of the order")]
+    VaultRequired,
+    #[msg("Order holds payments in receipts; buy it with lamports")]
+    PaymentsHeld,
+    #[msg("Order has taken its maximum number of fills")]
+    FillCapReached,
+    #[msg("Pass the seller's token account for the fill reaching the cap")]
+    SellerTokenAccountRequired,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A delegated order with default terms and its PDA bumps filled in.
+    fn order() -> SellOrder {
+        let mut order = SellOrder::from(SellOrderV1 {
+            seller: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            token_account: Pubkey::new_unique(),
+            amount_remaining: 1_000,
+            price_per_token: 10,
+            deadline: 2_000,
+            bump: 0,
+        });
+        order.order_id = 7;
+        order.created_at = 1_000;
+        order.last_fill_time = 1_000;
+        let [(_, order_bump), (_, authority_bump), (_, vault_bump)] = pdas(&order);
+        order.order_bump = order_bump;
+        order.authority_bump = authority_bump;
+        order.vault_bump = vault_bump;
+        order
+    }
+
+    /// Order, authority and vault PDAs of `order`, found without its bumps.
+    fn pdas(order: &SellOrder) -> [(Pubkey, u8); 3] {
+        let order_id = order.order_id.to_le_bytes();
+        let (creator, token_account) = (order.creator.as_ref(), order.token_account.as_ref());
+        [
+            Pubkey::find_program_address(
+                &[b"sell_order", creator, token_account, &order_id],
+                &crate::ID,
+            ),
+            Pubkey::find_program_address(&[creator, token_account], &crate::ID),
+            Pubkey::find_program_address(
+                &[b"vault", creator, order.token_mint.as_ref(), &order_id],
+                &crate::ID,
+            ),
+        ]
+    }
+
+    #[test]
+    fn stored_bumps_reproduce_pdas() {
+        let order = order();
+        let [(address, _), (authority, _), (vault, _)] = pdas(&order);
+        assert_eq!(order.order_address().ok(), Some(address));
+        assert_eq!(order.authority_address().ok(), Some(authority));
+        assert_eq!(order.vault_address().ok(), Some(vault));
+    }
+
+    #[test]
+    fn mismatched_bumps_are_detected() {
+        let mut order = order();
+        let address = order.order_address().unwrap();
+        let authority = order.authority_address().unwrap();
+        order.order_bump = order.order_bump.wrapping_sub(1);
+        order.authority_bump = order.authority_bump.wrapping_sub(1);
+        assert_ne!(order.order_address().ok(), Some(address));
+        assert_ne!(order.authority_address().ok(), Some(authority));
+    }
+
+    #[test]
+    fn fill_cap_ends_the_sale() {
+        let mut order = order();
+        order.max_fills = 2;
+        order.num_fills = 1;
+        assert!(!order.fills_capped());
+        assert!(order.validate_fill(1_500, 10).is_ok());
+        order.num_fills = 2;
+        assert!(order.fills_capped());
+        assert_eq!(
+            order.validate_fill(1_500, 10),
+            Err(SellError::FillCapReached.into())
+        );
+        // a capped order may be cancelled even while cancels are locked
+        order.cancellable_before_deadline = false;
+        assert!(order.require_cancellable(1_500).is_ok());
+    }
+
+    #[test]
+    fn closeout_discount_covers_only_its_window() {
+        let mut order = order();
+        order.closeout_bps = 5_000;
+        order.closeout_window = 100;
+        assert_eq!(order.price_at(1_900).unwrap(), 10);
+        assert_eq!(order.price_at(1_901).unwrap(), 5);
+        assert_eq!(order.price_at(2_000).unwrap(), 5);
+        order.closeout_window = 0;
+        assert_eq!(order.price_at(2_000).unwrap(), 10);
+    }
+
+    #[test]
+    fn allowance_backs_the_order() {
+        let order = order();
+        assert!(invariants::backed(&order, 1_000));
+        assert!(invariants::backed(&order, 5_000));
+        assert!(!invariants::backed(&order, 999));
+    }
+
+    #[test]
+    fn split_payment_sums_to_total() {
+        // xorshift, so the sweep is deterministic
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let edges = [0, 1, 2, 9_999, 10_000, 10_001, u64::MAX - 1, u64::MAX];
+        let bps = [0, 1, 50, 250, 999, 5_000, 9_999, 10_000];
+        for i in 0..2_000 {
+            let total = match i % 3 {
+                0 => edges[i % edges.len()],
+                1 => next() % 1_000_000,
+                _ => next(),
+            };
+            let fee_bps = if i % 2 == 0 {
+                bps[i % bps.len()]
+            } else {
+                (next() % 10_001) as u16
+            };
+            let royalty_bps = (next() % 10_001) as u16;
+            let (seller, fee, royalty) = split_payment(total, fee_bps, royalty_bps).unwrap();
+            assert_eq!(
+                seller as u128 + fee as u128 + royalty as u128,
+                total as u128
+            );
+            assert_eq!(fee, fee_for(total, fee_bps).unwrap().min(total));
+            assert!(royalty <= royalty_for(total, royalty_bps).unwrap());
+        }
+    }
+
+    #[test]
+    fn split_payment_rounding_favours_the_seller() {
+        // fee 2.525 rounds up, royalty 1.01 rounds down, the seller keeps the rest
+        assert_eq!(split_payment(101, 250, 100).unwrap(), (97, 3, 1));
+        assert_eq!(split_payment(1, 1, 9_999).unwrap(), (0, 1, 0));
+        assert_eq!(split_payment(100, 0, 0).unwrap(), (100, 0, 0));
+        // fee and royalty never take more than the total between them
+        assert_eq!(split_payment(100, 10_000, 10_000).unwrap(), (0, 100, 0));
+        assert_eq!(split_payment(100, 6_000, 6_000).unwrap(), (0, 60, 40));
+    }
+
+    #[test]
+    fn compute_totals_at_the_u64_boundary() {
+        assert_eq!(
+            compute_totals(u64::MAX, 1, 0, 0).unwrap(),
+            (u64::MAX, u64::MAX, 0, 0)
+        );
+        let (total, seller, fee, royalty) =
+            compute_totals(1 << 32, (1 << 32) - 1, 10_000, 0).unwrap();
+        assert_eq!(
+            (total, seller, fee, royalty),
+            (u64::MAX - (1 << 32) + 1, 0, total, 0)
+        );
+        assert_eq!(
+            compute_totals(1 << 32, 1 << 32, 0, 0),
+            Err(SellError::MathOverflow.into())
+        );
+        assert_eq!(
+            compute_totals(u64::MAX, u64::MAX, 250, 500),
+            Err(SellError::MathOverflow.into())
+        );
+        // full‑range fees on the largest total still fit
+        let (total, seller, fee, royalty) = compute_totals(u64::MAX, 1, 9_999, 1).unwrap();
+        assert_eq!(total, u64::MAX);
+        assert_eq!(
+            seller as u128 + fee as u128 + royalty as u128,
+            u64::MAX as u128
+        );
+    }
+
+    #[test]
+    fn quote_without_fees_charges_the_subtotal() {
+        let order = order();
+        let quote = quote(&order, 7, 0, 1_500).unwrap();
+        assert_eq!(
+            (quote.subtotal, quote.fee, quote.royalty, quote.total),
+            (70, 0, 0, 70)
+        );
+        assert_eq!(quote.proceeds().unwrap(), 70);
+    }
+
+    #[test]
+    fn quote_carves_fee_and_royalty_out_of_proceeds() {
+        let mut order = order();
+        order.royalty_bps = 500;
+        let quote = quote(&order, 7, 250, 1_500).unwrap();
+        assert_eq!(quote.price_per_token, 10);
+        assert_eq!(
+            (quote.subtotal, quote.fee, quote.royalty, quote.total),
+            (70, 2, 3, 70)
+        );
+        assert_eq!(quote.proceeds().unwrap(), 65);
+    }
+
+    #[test]
+    fn quote_overflow_fails() {
+        let mut order = order();
+        order.price_per_token = u64::MAX;
+        assert_eq!(quote(&order, 1, 10_000, 1_500).unwrap().total, u64::MAX);
+        assert_eq!(
+            quote(&order, 2, 0, 1_500),
+            Err(SellError::MathOverflow.into())
+        );
+    }
+
+    fn dutch(start_price: u64, end_price: u64) -> SellOrder {
+        let mut order = order();
+        order.pricing_mode = PricingMode::DutchAuction;
+        order.start_price = start_price;
+        order.end_price = end_price;
+        order
+    }
+
+    #[test]
+    fn dutch_price_hits_both_ends() {
+        let order = dutch(1_000, 100);
+        assert_eq!(order.price_at(900).unwrap(), 1_000);
+        assert_eq!(order.price_at(1_000).unwrap(), 1_000);
+        assert_eq!(order.price_at(1_500).unwrap(), 550);
+        assert_eq!(order.price_at(2_000).unwrap(), 100);
+    }
+
+    #[test]
+    fn dutch_price_rounds_up_between_integers() {
+        // 0.9 and 2.7 of a lamport accrued: the price only drops by whole lamports
+        let order = dutch(1_000, 100);
+        assert_eq!(order.price_at(1_001).unwrap(), 1_000);
+        assert_eq!(order.price_at(1_003).unwrap(), 998);
+    }
+
+    #[test]
+    fn dutch_price_stops_at_the_reserve() {
+        let mut order = dutch(1_000, 100);
+        order.reserve_price = 600;
+        assert_eq!(order.price_at(1_400).unwrap(), 640);
+        assert_eq!(order.price_at(1_900).unwrap(), 600);
+    }
+
+    #[test]
+    fn order_round_trips_through_account_data() {
+        let mut order = order();
+        order.label[..4].copy_from_slice(b"drop");
+        let mut data = Vec::new();
+        order.try_serialize(&mut data).unwrap();
+        // accounts are allocated at the full size, unused space zeroed
+        data.resize(8 + SellOrder::SIZE, 0);
+
+        let decoded = SellOrder::from_account_data(&data).unwrap();
+        assert_eq!(decoded.try_to_vec().unwrap(), order.try_to_vec().unwrap());
+
+        assert_eq!(
+            SellOrder::from_account_data(&data[..data.len() - 1]).map(|_| ()),
+            Err(SellError::InvalidOrderLayout.into())
+        );
+        data[0] ^= 1;
+        assert!(SellOrder::from_account_data(&data).is_err());
+    }
+
+    #[test]
+    fn prices_on_the_tick_grid() {
+        assert!(on_tick(1_234, 0));
+        assert!(on_tick(0, 100));
+        assert!(on_tick(500, 100));
+        assert!(!on_tick(550, 100));
+        assert!(on_tick(u64::MAX, 1));
+        assert!(!on_tick(u64::MAX, 2));
+    }
+
+    #[test]
+    fn token_amounts_convert_between_units() {
+        assert_eq!(to_base_units(3, 0).unwrap(), 3);
+        assert_eq!(to_base_units(3, 9).unwrap(), 3_000_000_000);
+        assert_eq!(to_base_units(18, 18).unwrap(), 18 * 10u64.pow(18));
+        assert_eq!(to_base_units(19, 18), Err(SellError::MathOverflow.into()));
+        assert_eq!(to_base_units(1, 20), Err(SellError::MathOverflow.into()));
+
+        assert_eq!(to_ui_amount(3_000_000_001, 9).unwrap(), (3, 1));
+        assert_eq!(to_ui_amount(999, 3).unwrap(), (0, 999));
+        assert_eq!(to_ui_amount(u64::MAX, 0).unwrap(), (u64::MAX, 0));
+        assert_eq!(to_ui_amount(1, 20), Err(SellError::MathOverflow.into()));
+    }
+
+    #[test]
+    fn tiers_price_by_quantity() {
+        let mut order = order();
+        order.tiers = vec![
+            PriceTier {
+                min_quantity: 100,
+                price_per_token: 9,
+            },
+            PriceTier {
+                min_quantity: 500,
+                price_per_token: 8,
+            },
+        ];
+        assert_eq!(order.price_for(99, 1_500).unwrap(), 10);
+        assert_eq!(order.price_for(100, 1_500).unwrap(), 9);
+        assert_eq!(order.price_for(499, 1_500).unwrap(), 9);
+        assert_eq!(order.price_for(500, 1_500).unwrap(), 8);
+        assert_eq!(order.price_for(u64::MAX, 1_500).unwrap(), 8);
+        // a tier never charges more than the base price
+        order.price_per_token = 7;
+        assert_eq!(order.price_for(100, 1_500).unwrap(), 7);
+    }
+
+    #[test]
+    fn price_time_accrues_between_fills() {
+        let mut order = order();
+        order.accrue_price_time(10, 1_100).unwrap();
+        assert_eq!(order.cumulative_price_time, 10 * 100);
+        assert_eq!(order.last_fill_time, 1_100);
+        order.accrue_price_time(20, 1_150).unwrap();
+        let before = order.cumulative_price_time;
+        assert_eq!(before, 1_000 + 20 * 50);
+        // two fills in the same second add nothing
+        order.accrue_price_time(99, 1_150).unwrap();
+        assert_eq!(order.cumulative_price_time, before);
+        // a clock behind the last fill never subtracts
+        order.accrue_price_time(99, 1_100).unwrap();
+        assert_eq!(order.cumulative_price_time, before);
+    }
+
+    #[test]
+    fn price_time_handles_the_largest_prices() {
+        let mut order = order();
+        order.accrue_price_time(u64::MAX, i64::MAX).unwrap();
+        assert_eq!(
+            order.cumulative_price_time,
+            u64::MAX as u128 * (i64::MAX - 1_000) as u128
+        );
+    }
+
+    #[test]
+    fn grace_period_charges_the_premium() {
+        let mut order = order();
+        order.grace_period = 100;
+        order.grace_premium_bps = 1_500;
+        assert_eq!(order.price_at(2_000).unwrap(), 10);
+        // 10 + 1.5, rounded up
+        assert_eq!(order.price_at(2_001).unwrap(), 12);
+        assert!(order.validate_fill(2_100, 10).is_ok());
+        assert_eq!(
+            order.validate_fill(2_101, 10),
+            Err(SellError::OrderExpired.into())
+        );
+        // a Dutch auction's premium applies on top of its final price
+        let mut order = dutch(1_000, 100);
+        order.grace_period = 100;
+        order.grace_premium_bps = 1_000;
+        assert_eq!(order.price_at(2_050).unwrap(), 110);
+    }
+
+    #[test]
+    fn grace_period_without_a_premium_keeps_the_deadline_price() {
+        let mut order = order();
+        order.grace_period = 100;
+        assert_eq!(order.price_at(2_050).unwrap(), 10);
+        order.grace_period = 0;
+        assert_eq!(
+            order.validate_fill(2_001, 10),
+            Err(SellError::OrderExpired.into())
+        );
+    }
+
+    #[test]
+    fn index_add_skips_duplicates_and_respects_capacity() {
+        let mut sellers = SellerIndex {
+            seller: Pubkey::new_unique(),
+            orders: Vec::new(),
+            bump: 0,
+        };
+        let first = Pubkey::new_unique();
+        index_add(Some(&mut sellers), first).unwrap();
+        index_add(Some(&mut sellers), first).unwrap();
+        assert_eq!(sellers.orders, vec![first]);
+        for _ in 1..MAX_SELLER_ORDERS {
+            index_add(Some(&mut sellers), Pubkey::new_unique()).unwrap();
+        }
+        // a full seller index refuses the listing, a full mint index skips it
+        assert_eq!(
+            index_add(Some(&mut sellers), Pubkey::new_unique()),
+            Err(SellError::SellerIndexFull.into())
+        );
+        index_add(Some(&mut sellers), first).unwrap();
+        let mut mints = MintIndex {
+            mint: Pubkey::new_unique(),
+            orders: sellers.orders.clone(),
+            bump: 0,
+        };
+        index_add(Some(&mut mints), Pubkey::new_unique()).unwrap();
+        assert_eq!(mints.orders, sellers.orders);
+        index_add::<MintIndex>(None, first).unwrap();
+    }
+
+    #[test]
+    fn index_remove_frees_a_slot() {
+        let mut index = SellerIndex {
+            seller: Pubkey::new_unique(),
+            orders: (0..MAX_SELLER_ORDERS)
+                .map(|_| Pubkey::new_unique())
+                .collect(),
+            bump: 0,
+        };
+        let gone = index.orders[3];
+        let index = index_remove(Some(&mut index), &gone).unwrap();
+        assert!(!index.orders.contains(&gone));
+        assert_eq!(index.orders.len(), MAX_SELLER_ORDERS - 1);
+        // removing an order that is not listed changes nothing
+        let index = index_remove(Some(index), &gone).unwrap();
+        assert_eq!(index.orders.len(), MAX_SELLER_ORDERS - 1);
+        let next = Pubkey::new_unique();
+        index_add(Some(&mut *index), next).unwrap();
+        assert_eq!(index.orders.last(), Some(&next));
+        assert!(index_remove::<SellerIndex>(None, &next).is_none());
+    }
+
+    #[test]
+    fn indexes_list_their_own_orders() {
+        let order = order();
+        let sellers = SellerIndex {
+            seller: order.seller,
+            orders: Vec::new(),
+            bump: 0,
+        };
+        let mints = MintIndex {
+            mint: order.token_mint,
+            orders: Vec::new(),
+            bump: 0,
+        };
+        assert!(sellers.lists(&order) && mints.lists(&order));
+        let mut other = order.clone();
+        other.seller = Pubkey::new_unique();
+        other.token_mint = Pubkey::new_unique();
+        assert!(!sellers.lists(&other) && !mints.lists(&other));
+    }
+
+    #[test]
+    fn scarcity_steps_raise_the_price_once_per_threshold() {
+        let mut order = order();
+        order.price_per_token = 1_000;
+        order.price_step_bps = 1_000;
+        order.price_step_amount = 100;
+        let fill = |order: &mut SellOrder, amount| {
+            let before = order.total_filled;
+            order.total_filled += amount;
+            order.step_price(before).unwrap()
+        };
+        assert_eq!(fill(&mut order, 50), None);
+        // crossing 100 raises 10%, the crossing fill still paid the old price
+        assert_eq!(fill(&mut order, 100), Some(1_000));
+        assert_eq!(order.price_per_token, 1_100);
+        assert_eq!(fill(&mut order, 49), None);
+        // one fill across 200, 300 and 400 compounds three steps, rounding up
+        assert_eq!(fill(&mut order, 221), Some(1_100));
+        assert_eq!(order.price_per_token, 1_465);
+    }
+
+    #[test]
+    fn scarcity_steps_land_on_the_tick_grid_and_stop_at_the_cap() {
+        let mut order = order();
+        order.price_per_token = 1_000;
+        order.price_step_bps = 1_000;
+        order.price_step_amount = 100;
+        order.tick_size = 25;
+        order.total_filled = 100;
+        assert_eq!(order.step_price(0).unwrap(), Some(1_000));
+        assert_eq!(order.price_per_token, 1_100);
+        order.total_filled = 200;
+        assert_eq!(order.step_price(100).unwrap(), Some(1_100));
+        assert_eq!(order.price_per_token, 1_225);
+
+        // steps past MAX_PRICE_STEPS, e.g. after `increase_order`, are ignored
+        let price = order.price_per_token;
+        order.total_filled = (MAX_PRICE_STEPS + 5) * 100;
+        order.step_price(MAX_PRICE_STEPS * 100).unwrap();
+        assert_eq!(order.price_per_token, price);
+    }
+
+    #[test]
+    fn fill_feed_keeps_the_most_recent_fills() {
+        let mut feed = FillFeed {
+            sell_order: Pubkey::new_unique(),
+            fills: Vec::new(),
+            next: 0,
+            bump: 0,
+        };
+        let entry = |i: u64| FillEntry {
+            buyer: Pubkey::new_unique(),
+            amount: i,
+            price_per_token: 10,
+            timestamp: i as i64,
+        };
+        let total = FILL_FEED_LEN as u64 + 3;
+        for i in 0..total {
+            feed.record(entry(i));
+        }
+        assert_eq!(feed.fills.len(), FILL_FEED_LEN);
+        // `next` points at the oldest entry still held
+        assert_eq!(feed.next, 3);
+        let mut amounts: Vec<u64> = feed.fills.iter().map(|fill| fill.amount).collect();
+        amounts.rotate_left(feed.next as usize);
+        assert_eq!(amounts, (3..total).collect::<Vec<_>>());
+
+        let mut data = Vec::new();
+        feed.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + FillFeed::SIZE);
+    }
+
+    /// An account at `key` owned by `owner`, over leaked buffers so Anchor
+    /// account types can borrow it for the rest of the test.
+    fn account_info(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(key)),
+            false,
+            true,
+            Box::leak(Box::new(1_000_000_000)),
+            Box::leak(data.into_boxed_slice()),
+            Box::leak(Box::new(owner)),
+            false,
+            0,
+        )
+    }
+
+    /// `order` as a program account at its order PDA.
+    fn order_account(order: &SellOrder) -> Account<'static, SellOrder> {
+        let mut data = Vec::new();
+        order.try_serialize(&mut data).unwrap();
+        let info = account_info(pdas(order)[0].0, crate::ID, data);
+        Account::try_from(Box::leak(Box::new(info))).unwrap()
+    }
+
+    /// The order's token account with `delegated` tokens approved to its
+    /// authority, and that authority.
+    fn delegated_accounts(
+        order: &SellOrder,
+        delegated: u64,
+    ) -> (
+        InterfaceAccount<'static, TokenAccount>,
+        SystemAccount<'static>,
+    ) {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_spl::token::spl_token::state::{Account as SplAccount, AccountState};
+
+        let authority = pdas(order)[1].0;
+        let mut data = vec![0; SplAccount::LEN];
+        SplAccount {
+            mint: order.token_mint,
+            owner: order.seller,
+            amount: order.amount_remaining,
+            delegate: COption::Some(authority),
+            state: AccountState::Initialized,
+            delegated_amount: delegated,
+            ..SplAccount::default()
+        }
+        .pack_into_slice(&mut data);
+        let token_account = account_info(order.token_account, anchor_spl::token::ID, data);
+        let authority = account_info(authority, system_program::ID, Vec::new());
+        (
+            InterfaceAccount::try_from(Box::leak(Box::new(token_account))).unwrap(),
+            SystemAccount::try_from(Box::leak(Box::new(authority))).unwrap(),
+        )
+    }
+
+    #[test]
+    fn invariants_hold_for_a_consistent_order() {
+        let order = order();
+        invariants::order_pdas(&order_account(&order), &pdas(&order)[1].0);
+        let (token_account, authority) = delegated_accounts(&order, 1_000);
+        invariants::covered(&order, &token_account, &authority);
+    }
+
+    #[test]
+    #[cfg_attr(not(debug_assertions), ignore)]
+    #[should_panic(expected = "order_bump")]
+    fn invariants_trip_on_a_stale_order_bump() {
+        let order = order();
+        let mut account = order_account(&order);
+        account.order_bump = account.order_bump.wrapping_sub(1);
+        invariants::order_pdas(&account, &pdas(&order)[1].0);
+    }
+
+    #[test]
+    #[cfg_attr(not(debug_assertions), ignore)]
+    #[should_panic(expected = "authority_bump")]
+    fn invariants_trip_on_a_foreign_authority() {
+        let order = order();
+        invariants::order_pdas(&order_account(&order), &Pubkey::new_unique());
+    }
+
+    #[test]
+    #[cfg_attr(not(debug_assertions), ignore)]
+    #[should_panic(expected = "allowance")]
+    fn invariants_trip_on_a_short_allowance() {
+        let order = order();
+        let (token_account, authority) = delegated_accounts(&order, 999);
+        invariants::covered(&order, &token_account, &authority);
+    }
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+};
+use timed_sell_order::{
+    self as program, OrderStatus, PricingMode, SellError, SellOrder, MIN_DURATION,
+};
+
+const LOT: u64 = 10;
+
+async fn bid(
+    env: &mut Env,
+    listing: &Listing,
+    bidder: &Keypair,
+    previous_bidder: Option<Pubkey>,
+    bid: u64,
+) -> Result<(), solana_program_test::BanksClientError> {
+    let ix = instruction(
+        program::accounts::PlaceAuctionBid {
+            bidder: bidder.pubkey(),
+            sell_order: listing.sell_order,
+            previous_bidder,
+            whitelist: None,
+            config: config_address(),
+            system_program: system_program::ID,
+        },
+        program::instruction::PlaceAuctionBid { bid },
+    );
+    env.send(&[ix], &[bidder]).await
+}
+
+#[tokio::test]
+async fn auction_settles_once_to_the_high_bidder() {
+    let env = &mut Env::new().await;
+    let deadline = env.now().await + MIN_DURATION;
+    let mut terms = params(1, LOT, 1_000, deadline);
+    terms.pricing_mode = PricingMode::EnglishAuction;
+    let listing = env.list_escrow(terms).await;
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    let vault = order.token_account;
+
+    let (first, _) = env.buyer(&listing.mint).await;
+    let (second, second_tokens) = env.buyer(&listing.mint).await;
+    let first_start = env.lamports(&first.pubkey()).await;
+    assert_error(
+        bid(env, &listing, &first, None, 9_999).await,
+        SellError::AuctionBidTooLow,
+    );
+    bid(env, &listing, &first, None, 20_000).await.unwrap();
+    assert_eq!(env.lamports(&first.pubkey()).await, first_start - 20_000);
+    assert_error(
+        bid(env, &listing, &second, Some(first.pubkey()), 20_000).await,
+        SellError::AuctionBidTooLow,
+    );
+    // outbidding refunds the previous high bid in the same instruction
+    bid(env, &listing, &second, Some(first.pubkey()), 30_000)
+        .await
+        .unwrap();
+    assert_eq!(env.lamports(&first.pubkey()).await, first_start);
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert_eq!(
+        (order.high_bid, order.high_bidder),
+        (30_000, Some(second.pubkey()))
+    );
+
+    // a stray deposit keeps the vault, and so the order, open after settling
+    env.mint_to(&listing.mint, &vault, 1).await;
+    let seller = listing.seller.pubkey();
+    let settle = instruction(
+        program::accounts::SettleAuction {
+            seller,
+            sell_order: listing.sell_order,
+            token_account: vault,
+            token_mint: listing.mint,
+            winner_token_account: second_tokens,
+            order_authority: authority_address(&seller, &vault),
+            config: config_address(),
+            proceeds: seller,
+            treasury: env.treasury,
+            royalty_creator: None,
+            seller_index: None,
+            mint_index: None,
+            token_program: spl_token::ID,
+        },
+        program::instruction::SettleAuction {},
+    );
+    assert_error(
+        env.send(std::slice::from_ref(&settle), &[]).await,
+        SellError::DeadlineNotReached,
+    );
+    env.warp_to(deadline + 1).await;
+    let treasury = env.treasury;
+    let (seller_before, treasury_before) =
+        (env.lamports(&seller).await, env.lamports(&treasury).await);
+    env.send(std::slice::from_ref(&settle), &[]).await.unwrap();
+
+    let fee = 30_000 * FEE_BPS as u64 / 10_000;
+    assert_eq!(env.token_balance(&second_tokens).await, LOT);
+    assert_eq!(env.lamports(&seller).await, seller_before + 30_000 - fee);
+    assert_eq!(env.lamports(&treasury).await, treasury_before + fee);
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert!(order.status == OrderStatus::Filled);
+    assert_eq!(order.amount_remaining, 0);
+
+    env.warp_to(deadline + 2).await;
+    assert_error(env.send(&[settle], &[]).await, SellError::OrderNotActive);
+    assert_eq!(env.lamports(&seller).await, seller_before + 30_000 - fee);
+}
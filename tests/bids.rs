@@ -0,0 +1,141 @@
+mod common;
+
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+};
+use timed_sell_order::{self as program, SellError, SellOrder};
+
+const AMOUNT: u64 = 1_000;
+const PRICE: u64 = 5_000;
+
+fn bid_address(sell_order: &Pubkey, bidder: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"bid", sell_order.as_ref(), bidder.as_ref()],
+        &program::ID,
+    )
+    .0
+}
+
+async fn post_bid(
+    env: &mut Env,
+    listing: &Listing,
+    bidder: &Keypair,
+    amount: u64,
+    price_per_token: u64,
+) -> Result<(), solana_program_test::BanksClientError> {
+    let ix = instruction(
+        program::accounts::CreateBid {
+            bidder: bidder.pubkey(),
+            sell_order: listing.sell_order,
+            bid: bid_address(&listing.sell_order, &bidder.pubkey()),
+            system_program: system_program::ID,
+        },
+        program::instruction::CreateBid {
+            amount,
+            price_per_token,
+        },
+    );
+    env.send(&[ix], &[bidder]).await
+}
+
+async fn listing(env: &mut Env) -> Listing {
+    let deadline = env.now().await + 3_600;
+    env.list(params(1, AMOUNT, PRICE, deadline)).await
+}
+
+#[tokio::test]
+async fn accepted_bid_settles_at_the_bid_price() {
+    let env = &mut Env::new().await;
+    let listing = listing(env).await;
+    let (bidder, bidder_tokens) = env.buyer(&listing.mint).await;
+    let bid = bid_address(&listing.sell_order, &bidder.pubkey());
+    let bidder_start = env.lamports(&bidder.pubkey()).await;
+
+    post_bid(env, &listing, &bidder, 400, 4_000).await.unwrap();
+    let escrow = 400 * 4_000;
+    let bid_rent = env.lamports(&bid).await - escrow;
+    assert_eq!(
+        env.lamports(&bidder.pubkey()).await,
+        bidder_start - escrow - bid_rent
+    );
+
+    let seller = listing.seller.pubkey();
+    let treasury = env.treasury;
+    let (seller_before, treasury_before) =
+        (env.lamports(&seller).await, env.lamports(&treasury).await);
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    let accept = instruction(
+        program::accounts::AcceptBid {
+            seller,
+            sell_order: listing.sell_order,
+            bid,
+            bidder: bidder.pubkey(),
+            token_account: order.token_account,
+            token_mint: order.token_mint,
+            bidder_token_account: bidder_tokens,
+            seller_token_account: None,
+            order_authority: authority_address(&order.creator, &order.token_account),
+            whitelist: None,
+            purchase_record: None,
+            fill_feed: None,
+            seller_index: None,
+            mint_index: None,
+            config: config_address(),
+            proceeds: seller,
+            treasury,
+            royalty_creator: None,
+            token_program: spl_token::ID,
+        },
+        program::instruction::AcceptBid {},
+    );
+    env.send(&[accept], &[&listing.seller]).await.unwrap();
+
+    let fee = escrow * FEE_BPS as u64 / 10_000;
+    assert_eq!(env.token_balance(&bidder_tokens).await, 400);
+    assert_eq!(env.lamports(&seller).await, seller_before + escrow - fee);
+    assert_eq!(env.lamports(&treasury).await, treasury_before + fee);
+    // the emptied escrow is closed back to the bidder
+    assert!(env.account(&bid).await.is_none());
+    assert_eq!(env.lamports(&bidder.pubkey()).await, bidder_start - escrow);
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert_eq!(order.amount_remaining, AMOUNT - 400);
+}
+
+#[tokio::test]
+async fn cancelled_bid_returns_the_escrow() {
+    let env = &mut Env::new().await;
+    let listing = listing(env).await;
+    let (bidder, _) = env.buyer(&listing.mint).await;
+    let bid = bid_address(&listing.sell_order, &bidder.pubkey());
+    let bidder_start = env.lamports(&bidder.pubkey()).await;
+
+    post_bid(env, &listing, &bidder, 400, 4_000).await.unwrap();
+    let cancel = instruction(
+        program::accounts::CancelBid {
+            bidder: bidder.pubkey(),
+            bid,
+        },
+        program::instruction::CancelBid {},
+    );
+    env.send(&[cancel], &[&bidder]).await.unwrap();
+
+    assert!(env.account(&bid).await.is_none());
+    assert_eq!(env.lamports(&bidder.pubkey()).await, bidder_start);
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert_eq!(order.amount_remaining, AMOUNT);
+}
+
+#[tokio::test]
+async fn bid_for_more_than_remains_is_rejected() {
+    let env = &mut Env::new().await;
+    let listing = listing(env).await;
+    let (bidder, _) = env.buyer(&listing.mint).await;
+    assert_error(
+        post_bid(env, &listing, &bidder, AMOUNT + 1, 4_000).await,
+        SellError::InvalidAmount,
+    );
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use common::*;
+use solana_program_test::ProgramTest;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use timed_sell_order::{self as program, SellOrder};
+
+const AMOUNT: u64 = 1_000;
+const PRICE: u64 = 5_000;
+
+/// Two identical orders, and the instruction clearing each: `buy_all` on
+/// the first, `buy` of its whole remainder on the second.
+async fn twin_orders(env: &mut Env) -> Vec<(Listing, Instruction, Keypair, Pubkey)> {
+    let deadline = env.now().await + 3_600;
+    let mut twins = Vec::new();
+    for buy_all in [true, false] {
+        let listing = env.list(params(1, AMOUNT, PRICE, deadline)).await;
+        let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+        let (buyer, buyer_tokens) = env.buyer(&listing.mint).await;
+        let accounts = buy_accounts(
+            env,
+            &order,
+            listing.sell_order,
+            buyer.pubkey(),
+            buyer_tokens,
+        );
+        let ix = if buy_all {
+            instruction(
+                accounts,
+                program::instruction::BuyAll {
+                    max_price_per_token: PRICE,
+                    request_id: None,
+                },
+            )
+        } else {
+            instruction(
+                accounts,
+                program::instruction::Buy {
+                    amount: AMOUNT,
+                    max_price_per_token: PRICE,
+                    max_total_cost: u64::MAX,
+                    request_id: None,
+                },
+            )
+        };
+        twins.push((listing, ix, buyer, buyer_tokens));
+    }
+    twins
+}
+
+#[tokio::test]
+async fn buy_all_matches_buying_the_remainder() {
+    let env = &mut Env::new().await;
+    let mut outcomes = Vec::new();
+    for (listing, ix, buyer, buyer_tokens) in twin_orders(env).await {
+        let (seller, treasury) = (listing.seller.pubkey(), env.treasury);
+        let before = [
+            env.lamports(&buyer.pubkey()).await,
+            env.lamports(&seller).await,
+            env.lamports(&treasury).await,
+        ];
+        let order_rent = env.lamports(&listing.sell_order).await;
+        env.send(&[ix], &[&buyer]).await.unwrap();
+
+        assert!(env.account(&listing.sell_order).await.is_none());
+        assert_eq!(env.token_balance(&buyer_tokens).await, AMOUNT);
+        assert_eq!(env.token_balance(&listing.seller_tokens).await, 0);
+        outcomes.push([
+            before[0] - env.lamports(&buyer.pubkey()).await,
+            env.lamports(&seller).await - before[1] - order_rent,
+            env.lamports(&treasury).await - before[2],
+        ]);
+    }
+    let total = AMOUNT * PRICE;
+    let fee = total * FEE_BPS as u64 / 10_000;
+    assert_eq!(outcomes[0], [total, total - fee, fee]);
+    assert_eq!(outcomes[0], outcomes[1]);
+}
+
+/// Compute units only mean something for the SBF build, which the native
+/// test processor does not meter.  Run with the program built by
+/// `cargo build-sbf` and `SBF_OUT_DIR=target/deploy`.
+#[tokio::test]
+#[ignore = "needs the SBF build of the program"]
+async fn buy_all_costs_no_more_compute_than_buy() {
+    let mut pt = ProgramTest::new("timed_sell_order", program::ID, None);
+    pt.prefer_bpf(true);
+    let env = &mut Env::start(pt).await;
+    let mut units = Vec::new();
+    for (_, ix, buyer, _) in twin_orders(env).await {
+        let hash = env.ctx.banks_client.get_latest_blockhash().await.unwrap();
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&env.payer()),
+            &[&env.ctx.payer, &buyer],
+            hash,
+        );
+        let simulation = env.ctx.banks_client.simulate_transaction(tx).await.unwrap();
+        simulation.result.unwrap().unwrap();
+        units.push(simulation.simulation_details.unwrap().units_consumed);
+    }
+    println!("buy_all: {} CU, buy: {} CU", units[0], units[1]);
+    assert!(units[0] <= units[1]);
+}
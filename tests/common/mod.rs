@@ -0,0 +1,439 @@
+//! Shared setup for the integration tests: a bank running the program
+//! natively next to SPL Token, with the global config initialized.
+#![allow(dead_code)]
+
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use anchor_spl::token::spl_token;
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_instruction, system_program,
+    sysvar::clock::Clock,
+    transaction::{Transaction, TransactionError},
+};
+use timed_sell_order::{self as program, FillMode, OrderParams, PricingMode, SellError};
+
+/// Protocol fee the tests' config charges
+pub const FEE_BPS: u16 = 100;
+
+pub fn program_test() -> ProgramTest {
+    ProgramTest::new("timed_sell_order", program::ID, processor!(program::entry))
+}
+
+pub struct Env {
+    pub ctx: ProgramTestContext,
+    pub treasury: Pubkey,
+}
+
+impl Env {
+    pub async fn new() -> Self {
+        Self::start(program_test()).await
+    }
+
+    /// Start `pt` and create the global config, with a funded treasury.
+    pub async fn start(pt: ProgramTest) -> Self {
+        let ctx = pt.start_with_context().await;
+        let mut env = Self {
+            ctx,
+            treasury: Pubkey::new_unique(),
+        };
+        let treasury = env.treasury;
+        env.fund(&treasury, 1_000_000_000).await;
+        let admin = env.payer();
+        env.send(
+            &[Instruction {
+                program_id: program::ID,
+                accounts: program::accounts::InitializeConfig {
+                    admin,
+                    config: config_address(),
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: program::instruction::InitializeConfig {
+                    treasury,
+                    fee_bps: FEE_BPS,
+                }
+                .data(),
+            }],
+            &[],
+        )
+        .await
+        .unwrap();
+        env
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.ctx.payer.pubkey()
+    }
+
+    /// Send `ixs` paid by the context payer and signed by it and `signers`.
+    pub async fn send(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        let hash = self.ctx.banks_client.get_latest_blockhash().await?;
+        let mut all = vec![&self.ctx.payer];
+        all.extend_from_slice(signers);
+        let tx =
+            Transaction::new_signed_with_payer(ixs, Some(&self.ctx.payer.pubkey()), &all, hash);
+        self.ctx.banks_client.process_transaction(tx).await
+    }
+
+    pub async fn now(&mut self) -> i64 {
+        self.clock().await.unix_timestamp
+    }
+
+    async fn clock(&mut self) -> Clock {
+        self.ctx.banks_client.get_sysvar::<Clock>().await.unwrap()
+    }
+
+    /// Move to the next slot, which also gives a fresh blockhash, with the
+    /// clock at `unix_timestamp`.
+    pub async fn warp_to(&mut self, unix_timestamp: i64) {
+        let slot = self.clock().await.slot;
+        self.ctx.warp_to_slot(slot + 1).unwrap();
+        let mut clock = self.clock().await;
+        clock.unix_timestamp = unix_timestamp;
+        self.ctx.set_sysvar(&clock);
+    }
+
+    pub async fn fund(&mut self, to: &Pubkey, lamports: u64) {
+        let ix = system_instruction::transfer(&self.payer(), to, lamports);
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    /// A new wallet holding `lamports`.
+    pub async fn wallet(&mut self, lamports: u64) -> Keypair {
+        let wallet = Keypair::new();
+        self.fund(&wallet.pubkey(), lamports).await;
+        wallet
+    }
+
+    pub async fn lamports(&mut self, key: &Pubkey) -> u64 {
+        self.ctx.banks_client.get_balance(*key).await.unwrap()
+    }
+
+    pub async fn account(&mut self, key: &Pubkey) -> Option<Account> {
+        self.ctx.banks_client.get_account(*key).await.unwrap()
+    }
+
+    pub async fn anchor_account<T: AccountDeserialize>(&mut self, key: &Pubkey) -> T {
+        let account = self.account(key).await.expect("account exists");
+        T::try_deserialize(&mut &account.data[..]).unwrap()
+    }
+
+    /// A mint of `decimals`, with the context payer as mint authority.
+    pub async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint = Keypair::new();
+        self.create_mint_at(&mint, decimals).await;
+        mint.pubkey()
+    }
+
+    /// [`Env::create_mint`] at the address of `mint`.
+    pub async fn create_mint_at(&mut self, mint: &Keypair, decimals: u8) {
+        let payer = self.payer();
+        let space = spl_token::state::Mint::LEN;
+        let ixs = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                Rent::default().minimum_balance(space),
+                space as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                decimals,
+            )
+            .unwrap(),
+        ];
+        self.send(&ixs, &[mint]).await.unwrap();
+    }
+
+    /// A token account of `mint` owned by `owner`.
+    pub async fn token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let account = Keypair::new();
+        self.token_account_at(&account, mint, owner).await;
+        account.pubkey()
+    }
+
+    /// [`Env::token_account`] at the address of `account`.
+    pub async fn token_account_at(&mut self, account: &Keypair, mint: &Pubkey, owner: &Pubkey) {
+        let payer = self.payer();
+        let space = spl_token::state::Account::LEN;
+        let ixs = [
+            system_instruction::create_account(
+                &payer,
+                &account.pubkey(),
+                Rent::default().minimum_balance(space),
+                space as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::ID,
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ];
+        self.send(&ixs, &[account]).await.unwrap();
+    }
+
+    pub async fn mint_to(&mut self, mint: &Pubkey, account: &Pubkey, amount: u64) {
+        let payer = self.payer();
+        let ix =
+            spl_token::instruction::mint_to(&spl_token::ID, mint, account, &payer, &[], amount)
+                .unwrap();
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    pub async fn token_balance(&mut self, account: &Pubkey) -> u64 {
+        let account = self.account(account).await.expect("token account exists");
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    }
+}
+
+/// A live order and the accounts it was opened with.
+pub struct Listing {
+    pub seller: Keypair,
+    pub mint: Pubkey,
+    /// Seller’s token account: the delegated account, or the one an escrow
+    /// order was funded from
+    pub seller_tokens: Pubkey,
+    pub sell_order: Pubkey,
+}
+
+impl Env {
+    /// A new seller holding `params.amount` tokens of a new 0‑decimal mint,
+    /// listing them in a delegated order.
+    pub async fn list(&mut self, params: OrderParams) -> Listing {
+        let (seller, mint, seller_tokens) = self.seller_with_tokens(params.amount).await;
+        let sell_order = order_address(&seller.pubkey(), &seller_tokens, params.order_id);
+        let ix = instruction(
+            program::accounts::CreateSellOrder {
+                seller: seller.pubkey(),
+                seller_token_account: seller_tokens,
+                token_mint: mint,
+                order_authority: authority_address(&seller.pubkey(), &seller_tokens),
+                config: config_address(),
+                mint_allowlist: mint_allowlist_address(),
+                sell_order,
+                seller_index: None,
+                mint_index: None,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: solana_sdk::sysvar::rent::ID,
+            },
+            program::instruction::CreateSellOrder { params },
+        );
+        self.send(&[ix], &[&seller]).await.unwrap();
+        Listing {
+            seller,
+            mint,
+            seller_tokens,
+            sell_order,
+        }
+    }
+
+    /// [`Env::list`] in an escrow order, with the tokens moved to its vault.
+    pub async fn list_escrow(&mut self, params: OrderParams) -> Listing {
+        let (seller, mint, seller_tokens) = self.seller_with_tokens(params.amount).await;
+        let vault = vault_address(&seller.pubkey(), &mint, params.order_id);
+        let sell_order = order_address(&seller.pubkey(), &vault, params.order_id);
+        let ix = instruction(
+            program::accounts::CreateEscrowOrder {
+                seller: seller.pubkey(),
+                seller_token_account: seller_tokens,
+                token_mint: mint,
+                order_authority: authority_address(&seller.pubkey(), &vault),
+                vault,
+                config: config_address(),
+                mint_allowlist: mint_allowlist_address(),
+                sell_order,
+                seller_index: None,
+                mint_index: None,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: solana_sdk::sysvar::rent::ID,
+            },
+            program::instruction::CreateEscrowOrder { params },
+        );
+        self.send(&[ix], &[&seller]).await.unwrap();
+        Listing {
+            seller,
+            mint,
+            seller_tokens,
+            sell_order,
+        }
+    }
+
+    /// A funded seller and a token account of a new mint holding `amount`.
+    pub async fn seller_with_tokens(&mut self, amount: u64) -> (Keypair, Pubkey, Pubkey) {
+        let seller = self.wallet(1_000_000_000).await;
+        let mint = self.create_mint(0).await;
+        let tokens = self.token_account(&mint, &seller.pubkey()).await;
+        self.mint_to(&mint, &tokens, amount).await;
+        (seller, mint, tokens)
+    }
+
+    /// A funded buyer with an empty token account of `mint`.
+    pub async fn buyer(&mut self, mint: &Pubkey) -> (Keypair, Pubkey) {
+        let buyer = self.wallet(10_000_000_000).await;
+        let tokens = self.token_account(mint, &buyer.pubkey()).await;
+        (buyer, tokens)
+    }
+}
+
+pub fn config_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], &program::ID).0
+}
+
+pub fn mint_allowlist_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"mint_allowlist"], &program::ID).0
+}
+
+/// Order PDA of `creator` selling from `token_account`.
+pub fn order_address(creator: &Pubkey, token_account: &Pubkey, order_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"sell_order",
+            creator.as_ref(),
+            token_account.as_ref(),
+            &order_id.to_le_bytes(),
+        ],
+        &program::ID,
+    )
+    .0
+}
+
+/// Authority PDA signing for the tokens of an order on `token_account`.
+pub fn authority_address(creator: &Pubkey, token_account: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[creator.as_ref(), token_account.as_ref()], &program::ID).0
+}
+
+pub fn vault_address(creator: &Pubkey, mint: &Pubkey, order_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"vault",
+            creator.as_ref(),
+            mint.as_ref(),
+            &order_id.to_le_bytes(),
+        ],
+        &program::ID,
+    )
+    .0
+}
+
+/// Terms of a plain fixed‑price lamport order.
+pub fn params(order_id: u64, amount: u64, price_per_token: u64, deadline: i64) -> OrderParams {
+    OrderParams {
+        order_id,
+        amount,
+        price_per_token,
+        deadline,
+        start_time: 0,
+        payment_mint: None,
+        min_purchase: 0,
+        allowed_buyer: None,
+        payout: None,
+        escrow_proceeds: false,
+        max_per_buyer: 0,
+        pricing_mode: PricingMode::Fixed,
+        end_price: 0,
+        tiers: Vec::new(),
+        is_nft: false,
+        royalty_bps: 0,
+        royalty_creator: Pubkey::default(),
+        cancellable_before_deadline: true,
+        fill_mode: FillMode::Partial,
+        reserve_price: 0,
+        grace_period: 0,
+        grace_premium_bps: 0,
+        sol_price_per_token: 0,
+        tick_size: 0,
+        usd_price_per_token: 0,
+        oracle: Pubkey::default(),
+        price_step_bps: 0,
+        price_step_amount: 0,
+        dust_threshold: 0,
+        closeout_window: 0,
+        closeout_bps: 0,
+        reminder_window: 0,
+        cancel_authority: None,
+        label: [0; 32],
+        callback_program: None,
+        callback_required: false,
+        percentage_of_balance: None,
+        release_delay: 0,
+        max_fills: 0,
+    }
+}
+
+/// `buy` accounts for `buyer` on the order at `sell_order`, with every
+/// optional account left out.
+pub fn buy_accounts(
+    env: &Env,
+    order: &program::SellOrder,
+    sell_order: Pubkey,
+    buyer: Pubkey,
+    buyer_token_account: Pubkey,
+) -> program::accounts::Buy {
+    program::accounts::Buy {
+        buyer,
+        seller: order.seller,
+        sell_order,
+        token_account: order.token_account,
+        token_mint: order.token_mint,
+        buyer_token_account,
+        seller_token_account: None,
+        order_authority: authority_address(&order.creator, &order.token_account),
+        whitelist: None,
+        purchase_record: None,
+        fill_feed: None,
+        seller_index: None,
+        mint_index: None,
+        config: config_address(),
+        proceeds: order.payout.unwrap_or(order.seller),
+        treasury: env.treasury,
+        referrer: None,
+        payment_receipt: None,
+        fee_exemption: None,
+        royalty_creator: None,
+        oracle: None,
+        callback_program: None,
+        callback_state: None,
+        token_program: spl_token::ID,
+        system_program: system_program::ID,
+    }
+}
+
+pub fn instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: program::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `result` failed with the program error `expected`.
+pub fn assert_error(result: Result<(), BanksClientError>, expected: SellError) {
+    let code = u32::from(expected);
+    match result.map_err(|err| err.unwrap()) {
+        Err(TransactionError::InstructionError(_, InstructionError::Custom(actual))) => {
+            assert_eq!(actual, code, "expected {expected}")
+        }
+        other => panic!("expected {expected}, got {other:?}"),
+    }
+}
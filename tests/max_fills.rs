@@ -0,0 +1,96 @@
+mod common;
+
+use common::*;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signer};
+use timed_sell_order::{self as program, OrderParams, SellError, SellOrder};
+
+const AMOUNT: u64 = 1_000;
+const PRICE: u64 = 5_000;
+
+async fn capped(env: &mut Env, max_fills: u32) -> OrderParams {
+    let deadline = env.now().await + 3_600;
+    OrderParams {
+        max_fills,
+        ..params(1, AMOUNT, PRICE, deadline)
+    }
+}
+
+/// A buy of `amount` from the order at `sell_order`, paid by the context
+/// payer, passing `seller_token_account` for the rest of a capped escrow
+/// order.
+async fn buy(
+    env: &mut Env,
+    sell_order: Pubkey,
+    buyer_tokens: Pubkey,
+    seller_token_account: Option<Pubkey>,
+    amount: u64,
+) -> Instruction {
+    let order: SellOrder = env.anchor_account(&sell_order).await;
+    let buyer = env.payer();
+    instruction(
+        program::accounts::Buy {
+            seller_token_account,
+            ..buy_accounts(env, &order, sell_order, buyer, buyer_tokens)
+        },
+        program::instruction::Buy {
+            amount,
+            max_price_per_token: PRICE,
+            max_total_cost: u64::MAX,
+            request_id: None,
+        },
+    )
+}
+
+#[tokio::test]
+async fn capped_escrow_order_returns_the_rest_and_closes() {
+    let env = &mut Env::new().await;
+    let params = capped(env, 2).await;
+    let listing = env.list_escrow(params).await;
+    let vault = vault_address(&listing.seller.pubkey(), &listing.mint, 1);
+    let buyer_tokens = env.token_account(&listing.mint, &env.payer()).await;
+
+    let first = buy(env, listing.sell_order, buyer_tokens, None, 100).await;
+    env.send(&[first], &[]).await.unwrap();
+
+    // the capping fill has to say where the rest goes
+    let blind = buy(env, listing.sell_order, buyer_tokens, None, 200).await;
+    assert_error(
+        env.send(&[blind], &[]).await,
+        SellError::SellerTokenAccountRequired,
+    );
+
+    let last = buy(
+        env,
+        listing.sell_order,
+        buyer_tokens,
+        Some(listing.seller_tokens),
+        200,
+    )
+    .await;
+    env.send(&[last], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&buyer_tokens).await, 300);
+    assert_eq!(env.token_balance(&listing.seller_tokens).await, 700);
+    assert!(env.account(&vault).await.is_none());
+    assert!(env.account(&listing.sell_order).await.is_none());
+}
+
+#[tokio::test]
+async fn capped_delegated_order_stops_selling() {
+    let env = &mut Env::new().await;
+    let params = capped(env, 1).await;
+    let listing = env.list(params).await;
+    let buyer_tokens = env.token_account(&listing.mint, &env.payer()).await;
+
+    let first = buy(env, listing.sell_order, buyer_tokens, None, 100).await;
+    env.send(&[first], &[]).await.unwrap();
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert_eq!(order.num_fills, 1);
+    assert_eq!(order.amount_remaining, AMOUNT - 100);
+
+    let second = buy(env, listing.sell_order, buyer_tokens, None, 50).await;
+    assert_error(env.send(&[second], &[]).await, SellError::FillCapReached);
+    assert_eq!(
+        env.token_balance(&listing.seller_tokens).await,
+        AMOUNT - 100
+    );
+}
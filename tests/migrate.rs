@@ -0,0 +1,131 @@
+mod common;
+
+use anchor_lang::{AnchorSerialize, Discriminator};
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::{
+    account::Account,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+};
+use timed_sell_order::{self as program, SellOrder, SellOrderV1};
+
+/// An order written by the first deployed program, at its address without
+/// an `order_id`, is moved to the current layout and can be bought from.
+#[tokio::test]
+async fn migrates_a_v1_order() {
+    let seller = Keypair::new();
+    let token_account = Keypair::new();
+    let mint_decimals = 6;
+    let legacy_order = Pubkey::find_program_address(
+        &[
+            b"sell_order",
+            seller.pubkey().as_ref(),
+            token_account.pubkey().as_ref(),
+        ],
+        &program::ID,
+    )
+    .0;
+    let (authority, bump) = Pubkey::find_program_address(
+        &[seller.pubkey().as_ref(), token_account.pubkey().as_ref()],
+        &program::ID,
+    );
+
+    // the account as v1 allocated it; its mint is created once the bank runs
+    let mint = Keypair::new();
+    let mut data = SellOrder::discriminator().to_vec();
+    SellOrderV1 {
+        seller: seller.pubkey(),
+        token_mint: mint.pubkey(),
+        token_account: token_account.pubkey(),
+        amount_remaining: 500,
+        price_per_token: 1_000,
+        deadline: i64::MAX / 2,
+        bump,
+    }
+    .serialize(&mut data)
+    .unwrap();
+    data.resize(8 + SellOrderV1::SIZE, 0);
+    let mut pt = program_test();
+    pt.add_account(
+        legacy_order,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: program::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let env = &mut Env::start(pt).await;
+    env.create_mint_at(&mint, mint_decimals).await;
+    let mint = mint.pubkey();
+    env.fund(&seller.pubkey(), 1_000_000_000).await;
+    env.token_account_at(&token_account, &mint, &seller.pubkey())
+        .await;
+    env.mint_to(&mint, &token_account.pubkey(), 500).await;
+    let approve = spl_token::instruction::approve(
+        &spl_token::ID,
+        &token_account.pubkey(),
+        &authority,
+        &seller.pubkey(),
+        &[],
+        500,
+    )
+    .unwrap();
+    env.send(&[approve], &[&seller]).await.unwrap();
+
+    let legacy_rent = env.lamports(&legacy_order).await;
+    let seller_before = env.lamports(&seller.pubkey()).await;
+    let sell_order = order_address(&seller.pubkey(), &token_account.pubkey(), 3);
+    let migrate = instruction(
+        program::accounts::MigrateOrder {
+            payer: env.payer(),
+            seller: seller.pubkey(),
+            legacy_order,
+            token_account: token_account.pubkey(),
+            token_mint: mint,
+            sell_order,
+            system_program: system_program::ID,
+        },
+        program::instruction::MigrateOrder { order_id: 3 },
+    );
+    env.send(&[migrate], &[]).await.unwrap();
+
+    assert!(env.account(&legacy_order).await.is_none());
+    assert_eq!(
+        env.lamports(&seller.pubkey()).await,
+        seller_before + legacy_rent
+    );
+    let order: SellOrder = env.anchor_account(&sell_order).await;
+    assert_eq!(order.version, SellOrder::VERSION);
+    assert_eq!(order.order_id, 3);
+    assert_eq!(order.seller, seller.pubkey());
+    assert_eq!(order.creator, seller.pubkey());
+    assert_eq!(order.token_mint, mint);
+    assert_eq!(order.token_decimals, mint_decimals);
+    assert_eq!(order.amount_remaining, 500);
+    assert_eq!(order.price_per_token, 1_000);
+    assert_eq!(order.authority_bump, bump);
+    assert_eq!(order.created_at, env.now().await);
+
+    // the v1 delegation now backs the migrated order
+    let buyer = env.wallet(1_000_000_000).await;
+    let buyer_tokens = env.token_account(&mint, &buyer.pubkey()).await;
+    let buy = instruction(
+        buy_accounts(env, &order, sell_order, buyer.pubkey(), buyer_tokens),
+        program::instruction::Buy {
+            amount: 200,
+            max_price_per_token: 1_000,
+            max_total_cost: u64::MAX,
+            request_id: None,
+        },
+    );
+    env.send(&[buy], &[&buyer]).await.unwrap();
+    assert_eq!(env.token_balance(&buyer_tokens).await, 200);
+    let order: SellOrder = env.anchor_account(&sell_order).await;
+    assert_eq!(order.amount_remaining, 300);
+}
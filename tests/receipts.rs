@@ -0,0 +1,191 @@
+mod common;
+
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+};
+use timed_sell_order::{self as program, PaymentReceipt, SellError, SellOrder};
+
+const AMOUNT: u64 = 1_000;
+const PRICE: u64 = 5_000;
+const RELEASE_DELAY: i64 = 600;
+
+fn receipt_address(sell_order: &Pubkey, created_at: i64, fill_index: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"receipt",
+            sell_order.as_ref(),
+            &created_at.to_le_bytes(),
+            &fill_index.to_le_bytes(),
+        ],
+        &program::ID,
+    )
+    .0
+}
+
+async fn listing(env: &mut Env) -> Listing {
+    let deadline = env.now().await + 3_600;
+    env.list(program::OrderParams {
+        release_delay: RELEASE_DELAY,
+        ..params(1, AMOUNT, PRICE, deadline)
+    })
+    .await
+}
+
+/// Buy `amount` from the order at `sell_order` into a receipt, returning
+/// the receipt's address.
+async fn buy_held(
+    env: &mut Env,
+    sell_order: Pubkey,
+    buyer: &Keypair,
+    buyer_tokens: Pubkey,
+    amount: u64,
+) -> Pubkey {
+    let order: SellOrder = env.anchor_account(&sell_order).await;
+    let receipt = receipt_address(&sell_order, order.created_at, order.receipts_issued);
+    let buy = instruction(
+        program::accounts::Buy {
+            payment_receipt: Some(receipt),
+            ..buy_accounts(env, &order, sell_order, buyer.pubkey(), buyer_tokens)
+        },
+        program::instruction::Buy {
+            amount,
+            max_price_per_token: PRICE,
+            max_total_cost: u64::MAX,
+            request_id: None,
+        },
+    );
+    env.send(&[buy], &[buyer]).await.unwrap();
+    receipt
+}
+
+fn release(caller: Pubkey, receipt: Pubkey, held: &PaymentReceipt) -> Instruction {
+    instruction(
+        program::accounts::ReleasePayment {
+            caller,
+            payment_receipt: receipt,
+            buyer: held.buyer,
+            payee: held.payee,
+        },
+        program::instruction::ReleasePayment {},
+    )
+}
+
+/// Seller's share of a fill of `amount`, after the protocol fee.
+fn proceeds(amount: u64) -> u64 {
+    let total = amount * PRICE;
+    total - total * u64::from(FEE_BPS) / 10_000
+}
+
+#[tokio::test]
+async fn buyer_releases_a_held_payment() {
+    let env = &mut Env::new().await;
+    let listing = listing(env).await;
+    let seller = listing.seller.pubkey();
+    let (buyer, buyer_tokens) = env.buyer(&listing.mint).await;
+    let seller_before = env.lamports(&seller).await;
+
+    let receipt = buy_held(env, listing.sell_order, &buyer, buyer_tokens, 400).await;
+    assert_eq!(env.token_balance(&buyer_tokens).await, 400);
+    assert_eq!(env.lamports(&seller).await, seller_before);
+    let held: PaymentReceipt = env.anchor_account(&receipt).await;
+    assert_eq!(held.amount, proceeds(400));
+    assert_eq!(held.payee, seller);
+
+    // a stranger must wait for release_at
+    let stranger = env.wallet(1_000_000_000).await;
+    let early = release(stranger.pubkey(), receipt, &held);
+    assert_error(
+        env.send(&[early], &[&stranger]).await,
+        SellError::PaymentLocked,
+    );
+
+    let buyer_before = env.lamports(&buyer.pubkey()).await;
+    let rent = env.lamports(&receipt).await - held.amount;
+    env.send(&[release(buyer.pubkey(), receipt, &held)], &[&buyer])
+        .await
+        .unwrap();
+    assert_eq!(env.lamports(&seller).await, seller_before + proceeds(400));
+    assert_eq!(env.lamports(&buyer.pubkey()).await, buyer_before + rent);
+    assert!(env.account(&receipt).await.is_none());
+}
+
+#[tokio::test]
+async fn anyone_releases_a_payment_once_due() {
+    let env = &mut Env::new().await;
+    let listing = listing(env).await;
+    let seller = listing.seller.pubkey();
+    let (buyer, buyer_tokens) = env.buyer(&listing.mint).await;
+    let seller_before = env.lamports(&seller).await;
+
+    let receipt = buy_held(env, listing.sell_order, &buyer, buyer_tokens, 400).await;
+    let held: PaymentReceipt = env.anchor_account(&receipt).await;
+    env.warp_to(held.release_at).await;
+
+    let stranger = env.wallet(1_000_000_000).await;
+    env.send(&[release(stranger.pubkey(), receipt, &held)], &[&stranger])
+        .await
+        .unwrap();
+    assert_eq!(env.lamports(&seller).await, seller_before + proceeds(400));
+    assert!(env.account(&receipt).await.is_none());
+}
+
+#[tokio::test]
+async fn reopened_order_does_not_reuse_held_receipts() {
+    let env = &mut Env::new().await;
+    let listing = listing(env).await;
+    let seller = listing.seller.pubkey();
+    let (buyer, buyer_tokens) = env.buyer(&listing.mint).await;
+
+    // selling out closes the order, its receipt still held
+    let first = buy_held(env, listing.sell_order, &buyer, buyer_tokens, AMOUNT).await;
+    assert!(env.account(&listing.sell_order).await.is_none());
+
+    // the seller gets the tokens back and reopens the same order
+    let back = spl_token::instruction::transfer(
+        &spl_token::ID,
+        &buyer_tokens,
+        &listing.seller_tokens,
+        &buyer.pubkey(),
+        &[],
+        AMOUNT,
+    )
+    .unwrap();
+    env.send(&[back], &[&buyer]).await.unwrap();
+    let now = env.now().await;
+    env.warp_to(now + 1).await;
+    let reopen = instruction(
+        program::accounts::CreateSellOrder {
+            seller,
+            seller_token_account: listing.seller_tokens,
+            token_mint: listing.mint,
+            order_authority: authority_address(&seller, &listing.seller_tokens),
+            config: config_address(),
+            mint_allowlist: mint_allowlist_address(),
+            sell_order: listing.sell_order,
+            seller_index: None,
+            mint_index: None,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            rent: solana_sdk::sysvar::rent::ID,
+        },
+        program::instruction::CreateSellOrder {
+            params: program::OrderParams {
+                release_delay: RELEASE_DELAY,
+                ..params(1, AMOUNT, PRICE, now + 3_600)
+            },
+        },
+    );
+    env.send(&[reopen], &[&listing.seller]).await.unwrap();
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert_eq!(order.receipts_issued, 0);
+
+    let second = buy_held(env, listing.sell_order, &buyer, buyer_tokens, 400).await;
+    assert_ne!(first, second);
+    let held: PaymentReceipt = env.anchor_account(&first).await;
+    assert_eq!(held.amount, proceeds(AMOUNT));
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::{instruction::AccountMeta, signature::Signer};
+use timed_sell_order::{self as program, SellOrder};
+
+const PRICE: u64 = 5_000;
+
+/// A buy larger than the order's own account drains it, then takes the
+/// rest from the extra source.
+#[tokio::test]
+async fn buy_spans_two_source_accounts() {
+    let env = &mut Env::new().await;
+    let deadline = env.now().await + 3_600;
+    let listing = env.list(params(1, 300, PRICE, deadline)).await;
+    let seller = listing.seller.pubkey();
+    let extra = env.token_account(&listing.mint, &seller).await;
+    env.mint_to(&listing.mint, &extra, 500).await;
+    let extra_authority = authority_address(&seller, &extra);
+    let add = instruction(
+        program::accounts::AddSource {
+            seller,
+            sell_order: listing.sell_order,
+            source_token_account: extra,
+            source_authority: extra_authority,
+            token_program: spl_token::ID,
+        },
+        program::instruction::AddSource { amount: 500 },
+    );
+    env.send(&[add], &[&listing.seller]).await.unwrap();
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert_eq!(order.amount_remaining, 800);
+
+    let (buyer, buyer_tokens) = env.buyer(&listing.mint).await;
+    let mut buy = instruction(
+        buy_accounts(
+            env,
+            &order,
+            listing.sell_order,
+            buyer.pubkey(),
+            buyer_tokens,
+        ),
+        program::instruction::Buy {
+            amount: 600,
+            max_price_per_token: PRICE,
+            max_total_cost: u64::MAX,
+            request_id: None,
+        },
+    );
+    buy.accounts.extend([
+        AccountMeta::new(extra, false),
+        AccountMeta::new_readonly(extra_authority, false),
+    ]);
+    env.send(&[buy], &[&buyer]).await.unwrap();
+
+    assert_eq!(env.token_balance(&buyer_tokens).await, 600);
+    assert_eq!(env.token_balance(&listing.seller_tokens).await, 0);
+    assert_eq!(env.token_balance(&extra).await, 200);
+    let order: SellOrder = env.anchor_account(&listing.sell_order).await;
+    assert_eq!(order.amount_remaining, 200);
+}